@@ -0,0 +1,107 @@
+//! GLSL and WGSL to SPIR-V with naga, for antithesis' build script and hot reloading. Errors are
+//! the rendered diagnostics rather than an error type.
+
+use std::{fs, path::Path};
+
+use naga::{
+    back::spv,
+    front::{glsl, wgsl},
+    valid::{Capabilities, ValidationFlags, Validator},
+    ShaderStage,
+};
+
+/// Whether `compile` takes `path`, going by its extension: GLSL .vert, .frag and .comp files,
+/// and .wgsl files.
+pub fn is_shader(path: &Path) -> bool {
+    shader_kind(path).is_some()
+}
+
+/// Compiles the shader at `path`, returning readable diagnostics with the offending source lines
+/// if it has errors.
+pub fn compile(path: &Path) -> Result<Vec<u32>, String> {
+    let path_name = path.display().to_string();
+    let Some(stage) = shader_kind(path) else {
+        return Err(format!("{} isn't a GLSL or WGSL shader", path_name));
+    };
+
+    let source =
+        fs::read_to_string(path).map_err(|err| format!("failed to read {}: {}", path_name, err))?;
+
+    // WGSL gets its stages from its entry points
+    let module = match stage {
+        Some(stage) => glsl::Frontend::default()
+            .parse(&glsl::Options::from(stage), &source)
+            .map_err(|errors| format!("in {}:\n{}", path_name, errors.emit_to_string(&source)))?,
+        None => wgsl::parse_str(&source)
+            .map_err(|err| err.emit_to_string_with_path(&source, &path_name))?,
+    };
+
+    let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+        .validate(&module)
+        .map_err(|err| err.emit_to_string_with_path(&source, &path_name))?;
+
+    let mut options = spv::Options::default();
+    // GLSL shaders are written for Vulkan's clip space already, y pointing down, while WGSL's
+    // points up and needs naga to flip it
+    if stage.is_some() {
+        options.flags -= spv::WriterFlags::ADJUST_COORDINATE_SPACE;
+    }
+    spv::write_vec(&module, &info, &options, None)
+        .map_err(|err| format!("failed to write SPIR-V for {}: {}", path_name, err))
+}
+
+/// `Some(None)` for WGSL, which has no single stage.
+fn shader_kind(path: &Path) -> Option<Option<ShaderStage>> {
+    match path.extension()?.to_str()? {
+        "vert" => Some(Some(ShaderStage::Vertex)),
+        "frag" => Some(Some(ShaderStage::Fragment)),
+        "comp" => Some(Some(ShaderStage::Compute)),
+        "wgsl" => Some(None),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// OpFNegate, which naga uses to flip the position's y.
+    const OP_F_NEGATE: u32 = 127;
+
+    fn compile_source(name: &str, source: &str) -> Vec<u32> {
+        let dir = std::env::temp_dir().join(format!("antithesis-compile-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, source).unwrap();
+        let words = compile(&path);
+        fs::remove_file(&path).unwrap();
+        words.unwrap()
+    }
+
+    fn negates(words: &[u32]) -> bool {
+        // skip the header, then walk the instructions by their word counts
+        let mut rest = &words[5..];
+        while let Some(&word) = rest.first() {
+            if word & 0xffff == OP_F_NEGATE {
+                return true;
+            }
+            rest = &rest[(word >> 16) as usize..];
+        }
+        false
+    }
+
+    #[test]
+    fn only_wgsl_flips_the_position() {
+        let wgsl = compile_source(
+            "flip.wgsl",
+            "@vertex fn main() -> @builtin(position) vec4<f32> { return vec4<f32>(0.0, 1.0, 0.0, 1.0); }",
+        );
+        let glsl = compile_source(
+            "flip.vert",
+            "#version 450\nvoid main() { gl_Position = vec4(0.0, 1.0, 0.0, 1.0); }",
+        );
+
+        assert!(negates(&wgsl));
+        assert!(!negates(&glsl));
+    }
+}
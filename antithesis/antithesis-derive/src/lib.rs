@@ -0,0 +1,322 @@
+//! Derive macros for antithesis, re-exported from the main crate; see `antithesis::mesh` and
+//! `antithesis::pod`.
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{
+    parse_macro_input, spanned::Spanned, Attribute, Data, DeriveInput, Error, Expr, ExprLit,
+    Fields, Ident, Lit, LitInt, Member, Result, Type,
+};
+
+/// Implements `antithesis::mesh::Vertex` for a `#[repr(C)]` struct, with one attribute per field
+/// at consecutive locations starting from 0.
+///
+/// Field types map to formats like so, arrays having up to 4 components:
+///
+/// - `f32`, `[f32; N]` to `R32..._SFLOAT`
+/// - `u32`, `i32` and arrays of them to `R32..._UINT` and `R32..._SINT`
+/// - `[u8; N]`, `[i8; N]`, `[u16; N]`, `[i16; N]` to the normalized `UNORM` and `SNORM` formats
+/// - `[[f32; N]; M]` matrices to `M` attributes of `[f32; N]`, taking `M` locations
+///
+/// `#[location(n)]` on a field moves it (and the fields after it) to location `n`, which can't
+/// be taken by an earlier field, and `#[format(R8G8B8A8_UINT)]` picks the format by hand. The
+/// struct has to be `Pod` as well, usually through `#[derive(Pod)]`. On the struct,
+/// `#[vertex(binding = 1, per_instance)]` changes the binding and steps it per instance
+/// instead of per vertex.
+#[proc_macro_derive(VertexLayout, attributes(vertex, location, format))]
+pub fn derive_vertex_layout(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand_vertex_layout(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Implements `antithesis::pod::Pod` for a `#[repr(C)]` struct, checking at compile time that
+/// all of its fields are `Pod` and that there's no padding between or after them.
+#[proc_macro_derive(Pod)]
+pub fn derive_pod(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand_pod(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand_pod(input: &DeriveInput) -> Result<TokenStream> {
+    let fields = plain_struct_fields(input, "Pod")?;
+    let field_types: Vec<&Type> = fields.iter().map(|field| &field.ty).collect();
+
+    let name = &input.ident;
+    let padding_message = format!(
+        "{} has padding, add explicit fields to fill the gaps instead",
+        name
+    );
+    Ok(quote! {
+        const _: fn() = || {
+            fn assert_pod<T: ::antithesis::pod::Pod>() {}
+            #(assert_pod::<#field_types>();)*
+        };
+
+        // with repr(C), the fields only add up to the struct's size if there are no gaps
+        const _: () = assert!(
+            ::core::mem::size_of::<#name>() == 0 #(+ ::core::mem::size_of::<#field_types>())*,
+            #padding_message
+        );
+
+        // SAFETY: the fields are all Pod and the assertion above rules out padding
+        unsafe impl ::antithesis::pod::Pod for #name {}
+    })
+}
+
+/// The fields of `input`, as long as it's a non-generic `#[repr(C)]` struct.
+fn plain_struct_fields<'a>(input: &'a DeriveInput, derive_name: &str) -> Result<&'a Fields> {
+    if !is_repr_c(&input.attrs)? {
+        return Err(Error::new(
+            input.ident.span(),
+            format!(
+                "{} needs #[repr(C)], otherwise the field order and offsets aren't fixed",
+                derive_name
+            ),
+        ));
+    }
+    if !input.generics.params.is_empty() {
+        return Err(Error::new(
+            input.generics.span(),
+            format!("{} can't be derived for generic structs", derive_name),
+        ));
+    }
+
+    match &input.data {
+        Data::Struct(data) => Ok(&data.fields),
+        _ => Err(Error::new(
+            input.ident.span(),
+            format!("{} can only be derived for structs", derive_name),
+        )),
+    }
+}
+
+fn expand_vertex_layout(input: &DeriveInput) -> Result<TokenStream> {
+    let fields = plain_struct_fields(input, "VertexLayout")?;
+
+    let binding_options = BindingOptions::parse(&input.attrs)?;
+    let binding = binding_options.binding;
+    let input_rate = if binding_options.is_per_instance {
+        quote!(INSTANCE)
+    } else {
+        quote!(VERTEX)
+    };
+
+    let members: Vec<Member> = match fields {
+        Fields::Named(fields) => fields
+            .named
+            .iter()
+            .map(|field| Member::Named(field.ident.clone().unwrap()))
+            .collect(),
+        _ => fields
+            .iter()
+            .enumerate()
+            .map(|(index, _)| Member::from(index))
+            .collect(),
+    };
+
+    let mut attributes = vec![];
+    let mut used_locations = vec![];
+    let mut location = 0;
+    for (field, member) in fields.iter().zip(members) {
+        let options = FieldOptions::parse(&field.attrs)?;
+        if let Some(field_location) = options.location {
+            location = field_location;
+        }
+
+        let (format, column_count) = match options.format {
+            Some(format) => (format, 1),
+            None => field_format(&field.ty)?,
+        };
+
+        // matrix columns follow each other in memory and take a location each
+        let field_type = &field.ty;
+        let column_size = quote!(::core::mem::size_of::<#field_type>() / #column_count);
+        for column in 0..column_count {
+            if used_locations.contains(&location) {
+                return Err(Error::new(
+                    field.span(),
+                    format!("location {} is already taken by an earlier field", location),
+                ));
+            }
+            used_locations.push(location);
+
+            attributes.push(quote! {
+                vk::VertexInputAttributeDescription {
+                    location: #location,
+                    binding: #binding,
+                    format: vk::Format::#format,
+                    offset: (::core::mem::offset_of!(Self, #member)
+                        + #column * #column_size) as u32,
+                }
+            });
+            location += 1;
+        }
+    }
+
+    let name = &input.ident;
+    Ok(quote! {
+        // vertices get uploaded byte for byte
+        const _: fn() = || {
+            fn assert_pod<T: ::antithesis::pod::Pod>() {}
+            assert_pod::<#name>();
+        };
+
+        impl ::antithesis::mesh::Vertex for #name {
+            fn layout() -> ::antithesis::mesh::VertexLayout {
+                use ::antithesis::ash::vk;
+
+                ::antithesis::mesh::VertexLayout {
+                    bindings: vec![vk::VertexInputBindingDescription {
+                        binding: #binding,
+                        stride: ::core::mem::size_of::<Self>() as u32,
+                        input_rate: vk::VertexInputRate::#input_rate,
+                    }],
+                    attributes: vec![#(#attributes),*],
+                }
+            }
+        }
+    })
+}
+
+fn is_repr_c(attrs: &[Attribute]) -> Result<bool> {
+    let mut is_repr_c = false;
+
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("repr")) {
+        attr.parse_nested_meta(|meta| {
+            is_repr_c |= meta.path.is_ident("C");
+            // skip over arguments like align(16)
+            if meta.input.peek(syn::token::Paren) {
+                let _ = meta.input.parse::<proc_macro2::Group>()?;
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(is_repr_c)
+}
+
+/// From `#[vertex(...)]` on the struct.
+#[derive(Default)]
+struct BindingOptions {
+    binding: u32,
+    is_per_instance: bool,
+}
+
+impl BindingOptions {
+    fn parse(attrs: &[Attribute]) -> Result<Self> {
+        let mut options = BindingOptions::default();
+
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("vertex")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("binding") {
+                    options.binding = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+                    Ok(())
+                } else if meta.path.is_ident("per_instance") {
+                    options.is_per_instance = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `binding = n` or `per_instance`"))
+                }
+            })?;
+        }
+
+        Ok(options)
+    }
+}
+
+/// From `#[location(n)]` and `#[format(NAME)]` on a field.
+#[derive(Default)]
+struct FieldOptions {
+    location: Option<u32>,
+    format: Option<Ident>,
+}
+
+impl FieldOptions {
+    fn parse(attrs: &[Attribute]) -> Result<Self> {
+        let mut options = FieldOptions::default();
+
+        for attr in attrs {
+            if attr.path().is_ident("location") {
+                options.location = Some(attr.parse_args::<LitInt>()?.base10_parse()?);
+            } else if attr.path().is_ident("format") {
+                options.format = Some(attr.parse_args::<Ident>()?);
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// The `vk::Format` variant for a field of type `ty`, and how many locations it takes.
+fn field_format(ty: &Type) -> Result<(Ident, usize)> {
+    let unsupported = || {
+        Error::new(
+            ty.span(),
+            "unsupported vertex field type, pick its format with #[format(...)]",
+        )
+    };
+
+    let (scalar, component_count, column_count) = match ty {
+        Type::Path(_) => (scalar_name(ty).ok_or_else(unsupported)?, 1, 1),
+        Type::Array(array) => match &*array.elem {
+            Type::Array(column) => (
+                scalar_name(&column.elem).ok_or_else(unsupported)?,
+                array_len(&column.len).ok_or_else(unsupported)?,
+                array_len(&array.len).ok_or_else(unsupported)?,
+            ),
+            elem => (
+                scalar_name(elem).ok_or_else(unsupported)?,
+                array_len(&array.len).ok_or_else(unsupported)?,
+                1,
+            ),
+        },
+        _ => return Err(unsupported()),
+    };
+
+    let (bits, suffix) = match scalar.as_str() {
+        "f32" => (32, "SFLOAT"),
+        "u32" => (32, "UINT"),
+        "i32" => (32, "SINT"),
+        // smaller integers are nearly always colors or packed normals
+        "u16" if component_count > 1 => (16, "UNORM"),
+        "i16" if component_count > 1 => (16, "SNORM"),
+        "u8" if component_count > 1 => (8, "UNORM"),
+        "i8" if component_count > 1 => (8, "SNORM"),
+        _ => return Err(unsupported()),
+    };
+    if !(1..=4).contains(&component_count) || (column_count > 1 && suffix != "SFLOAT") {
+        return Err(unsupported());
+    }
+
+    let components: String = ["R", "G", "B", "A"][..component_count]
+        .iter()
+        .map(|component| format!("{}{}", component, bits))
+        .collect();
+
+    Ok((
+        Ident::new(&format!("{}_{}", components, suffix), Span::call_site()),
+        column_count,
+    ))
+}
+
+fn scalar_name(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Path(path) => path.path.get_ident().map(|ident| ident.to_string()),
+        _ => None,
+    }
+}
+
+fn array_len(len: &Expr) -> Option<usize> {
+    match len {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(len), ..
+        }) => len.base10_parse().ok(),
+        _ => None,
+    }
+}
@@ -0,0 +1,103 @@
+//! Watches a directory for shaders that change and recompiles them with antithesis-compile, for
+//! antithesis' hot reloading. Its own crate so the compiler is only linked into games that hot
+//! reload, while antithesis' build script depends on antithesis-compile alone.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+};
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use antithesis_compile::{compile, is_shader};
+
+pub use notify;
+
+/// Watches a directory of GLSL and WGSL shaders, recompiling the ones that change.
+pub struct ShaderWatcher {
+    // stops watching when dropped
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+}
+
+impl ShaderWatcher {
+    pub fn new(dir: &Path) -> notify::Result<Self> {
+        let (sender, events) = mpsc::channel();
+
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+        Ok(ShaderWatcher {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// The shaders that changed since the last call, each with its SPIR-V or the compiler's
+    /// diagnostics.
+    pub fn poll(&self) -> Vec<(PathBuf, Result<Vec<u32>, String>)> {
+        changed_shader_paths(self.events.try_iter())
+            .into_iter()
+            .map(|path| {
+                let words = compile(&path);
+                (path, words)
+            })
+            .collect()
+    }
+}
+
+/// The shaders that `events` created or changed, each once, in the order they first show up.
+fn changed_shader_paths(
+    events: impl IntoIterator<Item = notify::Result<notify::Event>>,
+) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = vec![];
+    for event in events {
+        match event {
+            // editors often save by writing a new file and renaming it over the old one
+            Ok(event) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+                for path in event.paths {
+                    if is_shader(&path) && !paths.contains(&path) {
+                        paths.push(path);
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(err) => tracing::warn!(%err, "error while watching shaders"),
+        }
+    }
+
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::{
+        event::{CreateKind, ModifyKind, RemoveKind},
+        Event,
+    };
+
+    use super::*;
+
+    #[test]
+    fn keeps_each_changed_shader_once() {
+        let events = [
+            Ok(Event::new(EventKind::Create(CreateKind::File))
+                .add_path("shaders/croak.frag".into())),
+            Ok(Event::new(EventKind::Modify(ModifyKind::Any))
+                .add_path("shaders/notes.txt".into())
+                .add_path("shaders/croak.vert".into())),
+            Err(notify::Error::generic("lost track of a file")),
+            Ok(Event::new(EventKind::Modify(ModifyKind::Any))
+                .add_path("shaders/croak.frag".into())),
+            Ok(Event::new(EventKind::Remove(RemoveKind::File)).add_path("shaders/sky.wgsl".into())),
+        ];
+
+        assert_eq!(
+            changed_shader_paths(events),
+            [
+                PathBuf::from("shaders/croak.frag"),
+                PathBuf::from("shaders/croak.vert"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,83 @@
+// With the `compile-shaders` feature, compiles the GLSL (.vert, .frag, .comp) and WGSL (.wgsl)
+// shaders in shaders/ to SPIR-V, and writes a module with a constant for each of them that
+// `antithesis::shaders` includes. Without it the checked-in .spv files are used as they are.
+
+fn main() {
+    // otherwise cargo reruns this whenever any file in the package changes
+    println!("cargo:rerun-if-changed=build.rs");
+
+    #[cfg(feature = "compile-shaders")]
+    compile_shaders();
+}
+
+#[cfg(feature = "compile-shaders")]
+fn compile_shaders() {
+    use std::{env, fmt::Write, fs, path::Path};
+
+    use antithesis_compile::{compile, is_shader};
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    println!("cargo:rerun-if-changed=shaders");
+
+    let mut paths: Vec<_> = fs::read_dir("shaders")
+        .expect("failed to read shaders/")
+        .map(|entry| entry.unwrap().path())
+        // .spv, compile.sh and whatever else lives there
+        .filter(|path| is_shader(path))
+        .collect();
+    // keeps the generated module the same between builds
+    paths.sort();
+
+    let mut module = String::new();
+    let mut failed = 0;
+    for path in paths {
+        println!("cargo:rerun-if-changed={}", path.display());
+
+        let words = match compile(&path) {
+            Ok(words) => words,
+            Err(diagnostic) => {
+                eprintln!("{}", diagnostic);
+                failed += 1;
+                continue;
+            }
+        };
+
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        fs::write(
+            Path::new(&out_dir).join(format!("{}.spv", file_name)),
+            bytes,
+        )
+        .unwrap();
+
+        writeln!(
+            module,
+            "/// `shaders/{file_name}` compiled to SPIR-V.\n\
+             pub const {}: &[u8] = include_bytes!(concat!(env!(\"OUT_DIR\"), \"/{file_name}.spv\"));",
+            constant_name(file_name),
+        )
+        .unwrap();
+    }
+
+    // the diagnostics have all been printed above, so just point at them
+    if failed > 0 {
+        panic!(
+            "{} shader(s) failed to compile, see the errors above",
+            failed
+        );
+    }
+
+    fs::write(Path::new(&out_dir).join("shaders.rs"), module).unwrap();
+}
+
+/// `croak.vert` becomes `CROAK_VERT`.
+#[cfg(feature = "compile-shaders")]
+fn constant_name(file_name: &str) -> String {
+    file_name
+        .chars()
+        .map(|char| match char {
+            'a'..='z' | 'A'..='Z' | '0'..='9' => char.to_ascii_uppercase(),
+            _ => '_',
+        })
+        .collect()
+}
@@ -1,5 +1,57 @@
-use antithesis::app::run_app;
+use antithesis::{app::run_app, config::AppConfig, error::Result, frame::FrameContext, game::Game};
+use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
 
-fn main() {
-    run_app();
+/// Prints the frame rate once a second and quits on escape.
+#[derive(Default)]
+struct Demo {
+    seconds: f32,
+    frames: u32,
+    is_escape_pressed: bool,
+}
+
+impl Game for Demo {
+    fn update(&mut self, dt: f32) {
+        self.seconds += dt;
+        self.frames += 1;
+
+        if self.seconds >= 1.0 {
+            println!("{:.0} fps", self.frames as f32 / self.seconds);
+            self.seconds = 0.0;
+            self.frames = 0;
+        }
+    }
+
+    fn render(&mut self, frame: &mut FrameContext) {
+        frame.draw(3, 1);
+
+        if self.is_escape_pressed {
+            frame.request_exit();
+        }
+    }
+
+    fn event(&mut self, event: &WindowEvent) {
+        if let WindowEvent::KeyboardInput {
+            input:
+                KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::Escape),
+                    ..
+                },
+            ..
+        } = event
+        {
+            self.is_escape_pressed = true;
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+
+    let config = AppConfig::new().title("Antithesis");
+    // edit shaders/croak.frag while it runs, with `--features hot-reload`
+    #[cfg(feature = "hot-reload")]
+    let config = config.hot_reload_shaders(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders"));
+
+    run_app(config, Demo::default())
 }
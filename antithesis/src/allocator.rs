@@ -0,0 +1,804 @@
+use std::ptr;
+
+use ash::vk;
+
+use crate::error::{AntithesisError, Result, VkResultExt};
+
+// big enough that a game needs only a handful, drivers guarantee at least 4096 allocations
+const DEFAULT_BLOCK_SIZE: vk::DeviceSize = 64 * 1024 * 1024;
+
+/// What an allocation is for, which decides which memory types it can come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryLocation {
+    /// Only touched by the GPU, like render targets and uploaded meshes.
+    GpuOnly,
+    /// Written by the CPU and read by the GPU, like staging buffers.
+    CpuToGpu,
+    /// Written by the GPU and read back by the CPU, like screenshots.
+    GpuToCpu,
+}
+
+impl MemoryLocation {
+    fn required_flags(self) -> vk::MemoryPropertyFlags {
+        match self {
+            MemoryLocation::GpuOnly => vk::MemoryPropertyFlags::empty(),
+            MemoryLocation::CpuToGpu | MemoryLocation::GpuToCpu => {
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT
+            }
+        }
+    }
+
+    fn preferred_flags(self) -> vk::MemoryPropertyFlags {
+        match self {
+            MemoryLocation::GpuOnly => vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            MemoryLocation::CpuToGpu => vk::MemoryPropertyFlags::empty(),
+            // reading uncached memory is painfully slow
+            MemoryLocation::GpuToCpu => vk::MemoryPropertyFlags::HOST_CACHED,
+        }
+    }
+
+    fn unwanted_flags(self) -> vk::MemoryPropertyFlags {
+        match self {
+            // leave the (often small) host-visible device memory to things that need it
+            MemoryLocation::GpuOnly => vk::MemoryPropertyFlags::HOST_VISIBLE,
+            MemoryLocation::CpuToGpu | MemoryLocation::GpuToCpu => vk::MemoryPropertyFlags::empty(),
+        }
+    }
+}
+
+/// Buffers and linear images can't share a `bufferImageGranularity` page with optimal images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceKind {
+    /// Buffers and linearly tiled images.
+    Linear,
+    /// Optimally tiled images.
+    Optimal,
+}
+
+pub struct AllocationInfo {
+    pub requirements: vk::MemoryRequirements,
+    pub location: MemoryLocation,
+    pub kind: ResourceKind,
+    /// Gives the resource a memory allocation of its own, e.g. for big render targets.
+    pub is_dedicated: bool,
+}
+
+/// A piece of device memory handed out by the `Allocator`; give it back with `Allocator::free`.
+#[derive(Debug)]
+pub struct Allocation {
+    memory: vk::DeviceMemory,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+    memory_type_index: u32,
+    block_id: u64,
+    id: u64,
+    // already offset, null unless the memory is host visible
+    mapped_ptr: *mut u8,
+}
+
+// SAFETY: `mapped_ptr` points into a mapping owned by the allocation's block, which stays mapped
+// until the block is freed, and that only happens once every allocation in it is freed. Shared
+// references only read through it and writing takes `&mut self`, like a `&mut [u8]` would.
+unsafe impl Send for Allocation {}
+unsafe impl Sync for Allocation {}
+
+impl Allocation {
+    pub fn memory(&self) -> vk::DeviceMemory {
+        self.memory
+    }
+
+    /// Where the allocation starts in `memory`, for binding.
+    pub fn offset(&self) -> vk::DeviceSize {
+        self.offset
+    }
+
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    pub fn memory_type_index(&self) -> u32 {
+        self.memory_type_index
+    }
+
+    /// The allocation's bytes, if it's host visible. The memory stays mapped for as long as
+    /// it's allocated.
+    pub fn mapped_slice(&self) -> Option<&[u8]> {
+        (!self.mapped_ptr.is_null())
+            .then(|| unsafe { std::slice::from_raw_parts(self.mapped_ptr, self.size as usize) })
+    }
+
+    pub fn mapped_slice_mut(&mut self) -> Option<&mut [u8]> {
+        (!self.mapped_ptr.is_null())
+            .then(|| unsafe { std::slice::from_raw_parts_mut(self.mapped_ptr, self.size as usize) })
+    }
+}
+
+/// The calls the allocator makes into the driver, so it can be tested without a GPU.
+pub trait MemoryBackend {
+    fn allocate(
+        &mut self,
+        memory_type_index: u32,
+        size: vk::DeviceSize,
+    ) -> Result<vk::DeviceMemory>;
+
+    /// Also unmaps it, if it was mapped.
+    fn free(&mut self, memory: vk::DeviceMemory);
+
+    /// Maps all of `memory`, which is always host visible.
+    fn map(&mut self, memory: vk::DeviceMemory, size: vk::DeviceSize) -> Result<*mut u8>;
+}
+
+pub struct DeviceMemoryBackend {
+    device: ash::Device,
+}
+
+impl DeviceMemoryBackend {
+    pub fn new(device: ash::Device) -> Self {
+        DeviceMemoryBackend { device }
+    }
+}
+
+impl MemoryBackend for DeviceMemoryBackend {
+    fn allocate(
+        &mut self,
+        memory_type_index: u32,
+        size: vk::DeviceSize,
+    ) -> Result<vk::DeviceMemory> {
+        let allocate_info = vk::MemoryAllocateInfo::builder()
+            .allocation_size(size)
+            .memory_type_index(memory_type_index);
+
+        unsafe {
+            self.device
+                .allocate_memory(&allocate_info, None)
+                .context("vkAllocateMemory", "memory block")
+        }
+    }
+
+    fn free(&mut self, memory: vk::DeviceMemory) {
+        unsafe { self.device.free_memory(memory, None) };
+    }
+
+    fn map(&mut self, memory: vk::DeviceMemory, size: vk::DeviceSize) -> Result<*mut u8> {
+        unsafe {
+            self.device
+                .map_memory(memory, 0, size, vk::MemoryMapFlags::empty())
+                .context("vkMapMemory", "memory block")
+                .map(|ptr| ptr as *mut u8)
+        }
+    }
+}
+
+struct Suballocation {
+    id: u64,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+    kind: ResourceKind,
+}
+
+struct MemoryBlock {
+    id: u64,
+    memory: vk::DeviceMemory,
+    size: vk::DeviceSize,
+    mapped_ptr: *mut u8,
+    // sorted by offset
+    suballocations: Vec<Suballocation>,
+    is_dedicated: bool,
+}
+
+// SAFETY: the mapping belongs to the block and is only unmapped when the block's memory is freed,
+// so it can move between threads with the block
+unsafe impl Send for MemoryBlock {}
+
+impl MemoryBlock {
+    /// First gap that fits, as the index to insert at and the offset to use.
+    fn find_space(
+        &self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+        kind: ResourceKind,
+        granularity: vk::DeviceSize,
+    ) -> Option<(usize, vk::DeviceSize)> {
+        let mut previous: Option<&Suballocation> = None;
+
+        for index in 0..=self.suballocations.len() {
+            let next = self.suballocations.get(index);
+
+            let mut offset = align_up(
+                previous.map_or(0, |previous| previous.offset + previous.size),
+                alignment,
+            );
+            if let Some(previous) = previous {
+                if previous.kind != kind
+                    && is_same_page(previous.offset + previous.size - 1, offset, granularity)
+                {
+                    offset = align_up(offset, granularity);
+                }
+            }
+
+            let end = offset + size;
+            let is_fitting = match next {
+                Some(next) => {
+                    end <= next.offset
+                        && (next.kind == kind || !is_same_page(end - 1, next.offset, granularity))
+                }
+                None => end <= self.size,
+            };
+            if is_fitting {
+                return Some((index, offset));
+            }
+
+            previous = next;
+        }
+
+        None
+    }
+}
+
+fn align_up(value: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    value.div_ceil(alignment.max(1)) * alignment.max(1)
+}
+
+fn is_same_page(a: vk::DeviceSize, b: vk::DeviceSize, page_size: vk::DeviceSize) -> bool {
+    a / page_size.max(1) == b / page_size.max(1)
+}
+
+/// Hands out pieces of large memory blocks instead of one driver allocation per resource, since
+/// drivers only allow a few thousand of those.
+pub struct Allocator<B: MemoryBackend> {
+    backend: B,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    buffer_image_granularity: vk::DeviceSize,
+    // indexed by memory type
+    blocks: Vec<Vec<MemoryBlock>>,
+    next_id: u64,
+}
+
+impl<B: MemoryBackend> Allocator<B> {
+    pub fn new(
+        backend: B,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        buffer_image_granularity: vk::DeviceSize,
+    ) -> Self {
+        Allocator {
+            backend,
+            memory_properties,
+            buffer_image_granularity,
+            blocks: (0..memory_properties.memory_type_count)
+                .map(|_| vec![])
+                .collect(),
+            next_id: 0,
+        }
+    }
+
+    /// Allocates from the best fitting memory type, falling back to the next best when one
+    /// runs out.
+    pub fn allocate(&mut self, info: &AllocationInfo) -> Result<Allocation> {
+        let memory_types = self.find_memory_types(info);
+        if memory_types.is_empty() {
+            return Err(AntithesisError::NoSuitableMemoryType {
+                type_bits: info.requirements.memory_type_bits,
+                properties: info.location.required_flags(),
+            });
+        }
+
+        let mut last_err = None;
+        for memory_type_index in memory_types {
+            match self.allocate_from_type(memory_type_index, info) {
+                Ok(allocation) => return Ok(allocation),
+                Err(
+                    err @ AntithesisError::Vulkan {
+                        result:
+                            vk::Result::ERROR_OUT_OF_DEVICE_MEMORY
+                            | vk::Result::ERROR_OUT_OF_HOST_MEMORY,
+                        ..
+                    },
+                ) => {
+                    tracing::debug!(memory_type_index, %err, "memory type is full, trying the next one");
+                    last_err = Some(err);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        Err(last_err.unwrap())
+    }
+
+    /// Returns `allocation`'s memory, releasing its block if that was the last thing in it.
+    pub fn free(&mut self, allocation: Allocation) {
+        let blocks = &mut self.blocks[allocation.memory_type_index as usize];
+        let Some(block_index) = blocks
+            .iter()
+            .position(|block| block.id == allocation.block_id)
+        else {
+            return;
+        };
+
+        let block = &mut blocks[block_index];
+        block
+            .suballocations
+            .retain(|suballocation| suballocation.id != allocation.id);
+
+        if block.suballocations.is_empty() {
+            let block = blocks.remove(block_index);
+            self.backend.free(block.memory);
+        }
+    }
+
+    /// Releases every block, whether or not everything in it was freed; has to happen before
+    /// the device is destroyed.
+    pub fn free_all(&mut self) {
+        for block in self.blocks.iter_mut().flat_map(|blocks| blocks.drain(..)) {
+            if !block.suballocations.is_empty() {
+                tracing::warn!(
+                    count = block.suballocations.len(),
+                    "freeing a memory block with allocations still in it"
+                );
+            }
+            self.backend.free(block.memory);
+        }
+    }
+
+    /// Memory types `info` can use, best first.
+    fn find_memory_types(&self, info: &AllocationInfo) -> Vec<u32> {
+        let required_flags = info.location.required_flags();
+        let score = |flags: vk::MemoryPropertyFlags| {
+            (flags & info.location.preferred_flags())
+                .as_raw()
+                .count_ones() as i32
+                - (flags & info.location.unwanted_flags())
+                    .as_raw()
+                    .count_ones() as i32
+        };
+
+        let mut memory_types: Vec<u32> = (0..self.memory_properties.memory_type_count)
+            .filter(|&index| {
+                info.requirements.memory_type_bits & (1 << index) != 0
+                    && self.memory_properties.memory_types[index as usize]
+                        .property_flags
+                        .contains(required_flags)
+            })
+            .collect();
+
+        // stable, so equally good types stay in the driver's order
+        memory_types.sort_by_key(|&index| {
+            -score(self.memory_properties.memory_types[index as usize].property_flags)
+        });
+
+        memory_types
+    }
+
+    fn block_size(&self, memory_type_index: u32) -> vk::DeviceSize {
+        let heap_index = self.memory_properties.memory_types[memory_type_index as usize].heap_index;
+        let heap_size = self.memory_properties.memory_heaps[heap_index as usize].size;
+
+        // small heaps (like 256MiB of host visible VRAM) shouldn't go in one block
+        DEFAULT_BLOCK_SIZE.min(heap_size / 8)
+    }
+
+    fn allocate_from_type(
+        &mut self,
+        memory_type_index: u32,
+        info: &AllocationInfo,
+    ) -> Result<Allocation> {
+        let size = info.requirements.size;
+        let alignment = info.requirements.alignment;
+        let block_size = self.block_size(memory_type_index);
+        let granularity = self.buffer_image_granularity;
+
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let is_dedicated = info.is_dedicated || size > block_size / 2;
+        if !is_dedicated {
+            let blocks = &mut self.blocks[memory_type_index as usize];
+            for block in blocks.iter_mut().filter(|block| !block.is_dedicated) {
+                if let Some((index, offset)) =
+                    block.find_space(size, alignment, info.kind, granularity)
+                {
+                    block.suballocations.insert(
+                        index,
+                        Suballocation {
+                            id,
+                            offset,
+                            size,
+                            kind: info.kind,
+                        },
+                    );
+                    return Ok(suballocation_in(block, memory_type_index, id, offset, size));
+                }
+            }
+        }
+
+        let block_size = if is_dedicated { size } else { block_size };
+        let block = self.create_block(memory_type_index, block_size, is_dedicated)?;
+        let blocks = &mut self.blocks[memory_type_index as usize];
+        blocks.push(block);
+
+        let block = blocks.last_mut().unwrap();
+        block.suballocations.push(Suballocation {
+            id,
+            offset: 0,
+            size,
+            kind: info.kind,
+        });
+        Ok(suballocation_in(block, memory_type_index, id, 0, size))
+    }
+
+    fn create_block(
+        &mut self,
+        memory_type_index: u32,
+        size: vk::DeviceSize,
+        is_dedicated: bool,
+    ) -> Result<MemoryBlock> {
+        let memory = self.backend.allocate(memory_type_index, size)?;
+
+        let is_host_visible = self.memory_properties.memory_types[memory_type_index as usize]
+            .property_flags
+            .contains(vk::MemoryPropertyFlags::HOST_VISIBLE);
+        let mapped_ptr = if is_host_visible {
+            match self.backend.map(memory, size) {
+                Ok(mapped_ptr) => mapped_ptr,
+                Err(err) => {
+                    self.backend.free(memory);
+                    return Err(err);
+                }
+            }
+        } else {
+            ptr::null_mut()
+        };
+
+        let id = self.next_id;
+        self.next_id += 1;
+
+        Ok(MemoryBlock {
+            id,
+            memory,
+            size,
+            mapped_ptr,
+            suballocations: vec![],
+            is_dedicated,
+        })
+    }
+}
+
+fn suballocation_in(
+    block: &MemoryBlock,
+    memory_type_index: u32,
+    id: u64,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+) -> Allocation {
+    let mapped_ptr = if block.mapped_ptr.is_null() {
+        ptr::null_mut()
+    } else {
+        unsafe { block.mapped_ptr.add(offset as usize) }
+    };
+
+    Allocation {
+        memory: block.memory,
+        offset,
+        size,
+        memory_type_index,
+        block_id: block.id,
+        id,
+        mapped_ptr,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ash::vk::Handle;
+
+    use super::*;
+
+    const MIB: vk::DeviceSize = 1024 * 1024;
+
+    /// Hands out fake memory handles, backed by real host memory when mapped.
+    #[derive(Default)]
+    struct FakeBackend {
+        next_handle: u64,
+        live: HashMap<u64, Vec<u8>>,
+        full_memory_types: Vec<u32>,
+        allocation_count: usize,
+    }
+
+    impl MemoryBackend for FakeBackend {
+        fn allocate(
+            &mut self,
+            memory_type_index: u32,
+            size: vk::DeviceSize,
+        ) -> Result<vk::DeviceMemory> {
+            if self.full_memory_types.contains(&memory_type_index) {
+                return Err(AntithesisError::Vulkan {
+                    call: "vkAllocateMemory",
+                    resource: "memory block",
+                    result: vk::Result::ERROR_OUT_OF_DEVICE_MEMORY,
+                });
+            }
+
+            self.next_handle += 1;
+            self.allocation_count += 1;
+            self.live.insert(self.next_handle, vec![0; size as usize]);
+            Ok(vk::DeviceMemory::from_raw(self.next_handle))
+        }
+
+        fn free(&mut self, memory: vk::DeviceMemory) {
+            assert!(
+                self.live.remove(&memory.as_raw()).is_some(),
+                "double free of {:?}",
+                memory
+            );
+        }
+
+        fn map(&mut self, memory: vk::DeviceMemory, _size: vk::DeviceSize) -> Result<*mut u8> {
+            Ok(self.live.get_mut(&memory.as_raw()).unwrap().as_mut_ptr())
+        }
+    }
+
+    /// A discrete-GPU-like layout: 0 is VRAM, 1 is uncached system memory, 2 is cached system
+    /// memory and 3 is the small host visible window into VRAM.
+    fn discrete_gpu_properties() -> vk::PhysicalDeviceMemoryProperties {
+        let mut properties = vk::PhysicalDeviceMemoryProperties {
+            memory_type_count: 4,
+            memory_heap_count: 3,
+            ..Default::default()
+        };
+
+        properties.memory_heaps[0] = vk::MemoryHeap {
+            size: 4096 * MIB,
+            flags: vk::MemoryHeapFlags::DEVICE_LOCAL,
+        };
+        properties.memory_heaps[1] = vk::MemoryHeap {
+            size: 8192 * MIB,
+            flags: vk::MemoryHeapFlags::empty(),
+        };
+        properties.memory_heaps[2] = vk::MemoryHeap {
+            size: 256 * MIB,
+            flags: vk::MemoryHeapFlags::DEVICE_LOCAL,
+        };
+
+        let host_visible =
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+        properties.memory_types[0] = vk::MemoryType {
+            property_flags: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            heap_index: 0,
+        };
+        properties.memory_types[1] = vk::MemoryType {
+            property_flags: host_visible,
+            heap_index: 1,
+        };
+        properties.memory_types[2] = vk::MemoryType {
+            property_flags: host_visible | vk::MemoryPropertyFlags::HOST_CACHED,
+            heap_index: 1,
+        };
+        properties.memory_types[3] = vk::MemoryType {
+            property_flags: host_visible | vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            heap_index: 2,
+        };
+
+        properties
+    }
+
+    fn allocator() -> Allocator<FakeBackend> {
+        Allocator::new(FakeBackend::default(), discrete_gpu_properties(), 1024)
+    }
+
+    fn info(
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+        location: MemoryLocation,
+    ) -> AllocationInfo {
+        AllocationInfo {
+            requirements: vk::MemoryRequirements {
+                size,
+                alignment,
+                memory_type_bits: !0,
+            },
+            location,
+            kind: ResourceKind::Linear,
+            is_dedicated: false,
+        }
+    }
+
+    #[test]
+    fn suballocates_from_one_block() {
+        let mut allocator = allocator();
+
+        let first = allocator
+            .allocate(&info(100, 256, MemoryLocation::GpuOnly))
+            .unwrap();
+        let second = allocator
+            .allocate(&info(100, 256, MemoryLocation::GpuOnly))
+            .unwrap();
+
+        assert_eq!(allocator.backend.allocation_count, 1);
+        assert_eq!(first.memory(), second.memory());
+        assert_eq!(first.offset(), 0);
+        assert_eq!(second.offset(), 256);
+    }
+
+    #[test]
+    fn picks_memory_types_by_location() {
+        let mut allocator = allocator();
+
+        let gpu_only = allocator
+            .allocate(&info(64, 16, MemoryLocation::GpuOnly))
+            .unwrap();
+        let upload = allocator
+            .allocate(&info(64, 16, MemoryLocation::CpuToGpu))
+            .unwrap();
+        let readback = allocator
+            .allocate(&info(64, 16, MemoryLocation::GpuToCpu))
+            .unwrap();
+
+        assert_eq!(gpu_only.memory_type_index(), 0);
+        assert_eq!(upload.memory_type_index(), 1);
+        assert_eq!(readback.memory_type_index(), 2);
+    }
+
+    #[test]
+    fn respects_memory_type_bits() {
+        let mut allocator = allocator();
+
+        let mut only_host_visible_vram = info(64, 16, MemoryLocation::GpuOnly);
+        only_host_visible_vram.requirements.memory_type_bits = 1 << 3;
+        let allocation = allocator.allocate(&only_host_visible_vram).unwrap();
+        assert_eq!(allocation.memory_type_index(), 3);
+
+        let mut no_host_visible = info(64, 16, MemoryLocation::CpuToGpu);
+        no_host_visible.requirements.memory_type_bits = 1 << 0;
+        assert!(matches!(
+            allocator.allocate(&no_host_visible),
+            Err(AntithesisError::NoSuitableMemoryType { .. })
+        ));
+    }
+
+    #[test]
+    fn pads_between_linear_and_optimal_resources() {
+        let mut allocator = allocator();
+
+        let buffer = allocator
+            .allocate(&info(100, 16, MemoryLocation::GpuOnly))
+            .unwrap();
+        let mut image_info = info(100, 16, MemoryLocation::GpuOnly);
+        image_info.kind = ResourceKind::Optimal;
+        let image = allocator.allocate(&image_info).unwrap();
+        let small_buffer = allocator
+            .allocate(&info(100, 16, MemoryLocation::GpuOnly))
+            .unwrap();
+        let large_buffer = allocator
+            .allocate(&info(2000, 16, MemoryLocation::GpuOnly))
+            .unwrap();
+
+        assert_eq!(buffer.offset(), 0);
+        // bumped to the next 1024 byte page, away from the buffer
+        assert_eq!(image.offset(), 1024);
+        // fits in the gap without touching the image's page
+        assert_eq!(small_buffer.offset(), 112);
+        // doesn't fit in the gap, so it goes after the image, on the page after its last one
+        assert_eq!(large_buffer.offset(), 2048);
+    }
+
+    #[test]
+    fn doesnt_pad_between_resources_of_the_same_kind() {
+        let mut allocator = allocator();
+
+        let first = allocator
+            .allocate(&info(100, 16, MemoryLocation::GpuOnly))
+            .unwrap();
+        let second = allocator
+            .allocate(&info(100, 16, MemoryLocation::GpuOnly))
+            .unwrap();
+
+        assert_eq!(first.offset(), 0);
+        assert_eq!(second.offset(), 112);
+    }
+
+    #[test]
+    fn large_and_dedicated_allocations_get_their_own_memory() {
+        let mut allocator = allocator();
+
+        let small = allocator
+            .allocate(&info(100, 16, MemoryLocation::GpuOnly))
+            .unwrap();
+        let large = allocator
+            .allocate(&info(48 * MIB, 16, MemoryLocation::GpuOnly))
+            .unwrap();
+        let mut dedicated_info = info(100, 16, MemoryLocation::GpuOnly);
+        dedicated_info.is_dedicated = true;
+        let dedicated = allocator.allocate(&dedicated_info).unwrap();
+        let other_small = allocator
+            .allocate(&info(100, 16, MemoryLocation::GpuOnly))
+            .unwrap();
+
+        assert_eq!(allocator.backend.allocation_count, 3);
+        assert_ne!(large.memory(), small.memory());
+        assert_ne!(dedicated.memory(), small.memory());
+        assert_eq!(other_small.memory(), small.memory());
+    }
+
+    #[test]
+    fn falls_back_to_the_next_memory_type_when_full() {
+        let mut allocator = allocator();
+        allocator.backend.full_memory_types.push(0);
+
+        let allocation = allocator
+            .allocate(&info(64, 16, MemoryLocation::GpuOnly))
+            .unwrap();
+
+        // the host visible part of VRAM is the next best thing for GPU-only resources
+        assert_eq!(allocation.memory_type_index(), 3);
+    }
+
+    #[test]
+    fn allocations_can_move_between_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        assert_send_sync::<Allocation>();
+        // the context shares it behind a mutex
+        assert_send_sync::<std::sync::Mutex<Allocator<DeviceMemoryBackend>>>();
+    }
+
+    #[test]
+    fn reuses_freed_space_and_releases_empty_blocks() {
+        let mut allocator = allocator();
+
+        let first = allocator
+            .allocate(&info(256, 256, MemoryLocation::GpuOnly))
+            .unwrap();
+        let second = allocator
+            .allocate(&info(256, 256, MemoryLocation::GpuOnly))
+            .unwrap();
+        allocator.free(first);
+
+        let third = allocator
+            .allocate(&info(256, 256, MemoryLocation::GpuOnly))
+            .unwrap();
+        assert_eq!(third.offset(), 0);
+
+        allocator.free(second);
+        allocator.free(third);
+        assert!(allocator.backend.live.is_empty());
+        assert!(allocator.blocks.iter().all(|blocks| blocks.is_empty()));
+    }
+
+    #[test]
+    fn host_visible_allocations_are_mapped() {
+        let mut allocator = allocator();
+
+        let gpu_only = allocator
+            .allocate(&info(64, 16, MemoryLocation::GpuOnly))
+            .unwrap();
+        assert!(gpu_only.mapped_slice().is_none());
+
+        let _first = allocator
+            .allocate(&info(64, 64, MemoryLocation::CpuToGpu))
+            .unwrap();
+        let mut second = allocator
+            .allocate(&info(64, 64, MemoryLocation::CpuToGpu))
+            .unwrap();
+        second.mapped_slice_mut().unwrap().fill(7);
+
+        let block = &allocator.backend.live[&second.memory().as_raw()];
+        assert!(block[..64].iter().all(|&byte| byte == 0));
+        assert!(block[64..128].iter().all(|&byte| byte == 7));
+    }
+
+    #[test]
+    fn free_all_releases_everything() {
+        let mut allocator = allocator();
+
+        allocator
+            .allocate(&info(64, 16, MemoryLocation::GpuOnly))
+            .unwrap();
+        allocator
+            .allocate(&info(64, 16, MemoryLocation::CpuToGpu))
+            .unwrap();
+        allocator.free_all();
+
+        assert!(allocator.backend.live.is_empty());
+    }
+}
@@ -1,50 +1,65 @@
 use crate::{
-    device::{create_logical_device, pick_physical_device},
-    pipeline::{create_framebuffers, create_gfx_pipeline, create_render_pass, create_vertex_buffer},
+    config::AppConfig,
+    context::VulkanContext,
+    error::{AntithesisError, Result, VkResultExt},
+    frame::FrameContext,
+    game::Game,
+    mesh::Mesh,
+    offscreen::{create_depth_target, destroy_offscreen_target, OffscreenTarget},
+    pipeline::{
+        create_framebuffers, create_gfx_pipeline, create_render_pass, create_triangle_mesh,
+        triangle_shaders, Pipeline, PipelineLayout,
+    },
+    readback::{is_readback_supported, read_image},
+    shader::Spirv,
     swapchain::{create_swapchain, SwapchainInfo},
     sync::{
-        create_command_buffers, create_command_pool, create_sync_objects, MAX_FRAMES_IN_FLIGHT,
+        begin_frame_commands, create_command_pool, create_frame_commands, create_sync_objects,
+        destroy_frame_commands, destroy_sync_objects, end_frame_commands, FrameCommands,
+        SyncObjects,
     },
 };
-
-use ash::{
-    extensions::khr::Surface,
-    vk::{self, ApplicationInfo},
-    Entry, Instance,
+#[cfg(feature = "hot-reload")]
+use crate::{
+    hot_reload::ShaderWatcher,
+    pipeline::{create_triangle_pipeline, TRIANGLE_SHADER_NAMES},
 };
+
+use ash::{extensions::khr::Surface, vk, Entry, Instance};
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
 use winit::{
-    event::{Event, WindowEvent},
+    event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
-    window::{Window, WindowBuilder},
+    window::{Fullscreen, Window, WindowBuilder},
 };
 
-use std::{ffi::CStr, os::raw::c_char};
+use std::{
+    mem::ManuallyDrop,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
 struct VulkanApp {
     window: Window,
-    entry: Entry,
-    instance: Instance,
-    surface_info: SurfaceInfo,
-
-    physical_device: vk::PhysicalDevice,
-    device: ash::Device, // Logical device
-
-    graphics_queue: vk::Queue,
-    present_queue: vk::Queue,
+    config: AppConfig,
+    context: VulkanContext,
 
     swapchain_info: SwapchainInfo,
+    // sized like the swapchain, so it's recreated along with it
+    depth_target: Option<OffscreenTarget>,
 
     render_pass: vk::RenderPass,
-    pipeline_layout: vk::PipelineLayout,
-    gfx_pipeline: vk::Pipeline,
+    pipeline_layout: PipelineLayout,
+    gfx_pipeline: Pipeline,
+    // what `gfx_pipeline` was built with, so hot reloaded shaders survive new render passes
+    triangle_shaders: [Spirv; 2],
     swapchain_framebuffers: Vec<vk::Framebuffer>,
 
-    vertex_buffer: vk::Buffer,
-    vertex_buffer_memory: vk::DeviceMemory,
+    // bound by default, so games can draw it without setting anything up
+    triangle: ManuallyDrop<Mesh>,
 
+    // for one-off work like screenshots, frames record into their own pools
     command_pool: vk::CommandPool,
-    command_buffers: Vec<vk::CommandBuffer>,
+    frame_commands: Vec<FrameCommands>,
 
     image_available_semaphores: Vec<vk::Semaphore>,
     render_finished_semaphores: Vec<vk::Semaphore>,
@@ -52,128 +67,165 @@ struct VulkanApp {
     current_frame: usize,
 
     is_framebuffer_resized: bool,
+    is_screenshot_requested: bool,
+
+    #[cfg(feature = "hot-reload")]
+    shader_watcher: Option<ShaderWatcher>,
 }
 
 impl VulkanApp {
-    fn initialize(window: Window) -> Self {
-        // Load vulkan through linking
-        let entry = ash::Entry::linked();
-
-        // Make instance
-        let instance = create_instance(&window, &entry);
-
-        // Create surface and other surface thing
-        let surface_info = SurfaceInfo::create(&window, &entry, &instance);
-
-        // Get physical device, logical device, and gfx queue
-        let physical_device = pick_physical_device(&instance, &surface_info);
-
-        let (device, queue_families) =
-            create_logical_device(&instance, &physical_device, &surface_info);
-
-        let graphics_queue =
-            unsafe { device.get_device_queue(queue_families.graphics_family.unwrap(), 0) };
-        let present_queue =
-            unsafe { device.get_device_queue(queue_families.present_family.unwrap(), 0) };
-
-        let swapchain_info = create_swapchain(&instance, &device, &physical_device, &surface_info);
-
-        let render_pass = create_render_pass(&device, &swapchain_info.swapchain_format);
-
-        let (pipeline_layout, gfx_pipeline) =
-            create_gfx_pipeline(&device, render_pass, &swapchain_info.swapchain_extent);
-
-        let swapchain_framebuffers = create_framebuffers(
-            &device,
-            render_pass,
-            &swapchain_info.swapchain_imageviews,
-            &swapchain_info.swapchain_extent,
-        );
-
-        let command_pool = create_command_pool(&device, &queue_families);
-
-        let (vertex_buffer, vertex_buffer_memory) =
-            create_vertex_buffer(&device, physical_device, &instance);
-
-        let command_buffers = create_command_buffers(
-            &device,
-            command_pool,
-            gfx_pipeline,
-            &swapchain_framebuffers,
-            render_pass,
-            swapchain_info.swapchain_extent,
-            vertex_buffer
-        );
-
-        let sync_objects = create_sync_objects(&device);
+    fn initialize(window: Window, config: AppConfig) -> Result<Self> {
+        // before any Vulkan objects, which would leak if this failed
+        #[cfg(feature = "hot-reload")]
+        let shader_watcher = config
+            .shader_dir
+            .as_ref()
+            .map(ShaderWatcher::new)
+            .transpose()?;
+
+        let triangle_shaders = triangle_shaders()?;
+
+        let context = VulkanContext::new(Some(&window), &config)?;
+
+        let mut parts = AppParts::default();
+        if let Err(err) = parts.create(&context, &window, &config, &triangle_shaders) {
+            // dropping the context destroys the device, which can't have anything left on it
+            parts.destroy(&context);
+            return Err(err);
+        }
+        let (pipeline_layout, gfx_pipeline) = parts.pipeline.unwrap();
+        let sync_objects = parts.sync_objects.unwrap();
 
-        VulkanApp {
+        Ok(VulkanApp {
             window,
-            entry,
-            instance,
-            surface_info,
-            physical_device,
-            device,
-            graphics_queue,
-            present_queue,
-            swapchain_info,
-            render_pass,
+            config,
+            context,
+            swapchain_info: parts.swapchain_info.unwrap(),
+            depth_target: parts.depth_target,
+            render_pass: parts.render_pass.unwrap(),
             pipeline_layout,
             gfx_pipeline,
-            swapchain_framebuffers,
-            vertex_buffer,
-            vertex_buffer_memory,
-            command_pool,
-            command_buffers,
+            triangle_shaders,
+            swapchain_framebuffers: parts.framebuffers,
+            triangle: ManuallyDrop::new(parts.triangle.unwrap()),
+            command_pool: parts.command_pool.unwrap(),
+            frame_commands: parts.frame_commands,
             image_available_semaphores: sync_objects.image_available_semaphores,
             render_finished_semaphores: sync_objects.render_finished_semaphores,
             in_flight_fences: sync_objects.inflight_fences,
             current_frame: 0,
             is_framebuffer_resized: false,
-        }
+            is_screenshot_requested: false,
+            #[cfg(feature = "hot-reload")]
+            shader_watcher,
+        })
     }
 
-    fn draw_frame(&mut self) {
+    /// Draws and presents one frame, returning whether the game asked to exit.
+    fn draw_frame(&mut self, game: &mut impl Game) -> Result<bool> {
+        // there's nothing to draw to (and no valid swapchain extent) while minimized
+        if self.is_minimized() {
+            return Ok(false);
+        }
+
+        #[cfg(feature = "hot-reload")]
+        self.reload_shaders(game)?;
+
         let wait_fences = [self.in_flight_fences[self.current_frame]];
 
-        let (image_index, _is_sub_optimal) = unsafe {
-            self.device
-                .wait_for_fences(&wait_fences, true, std::u64::MAX)
-                .expect("Failed to wait for Fence!");
+        let result = unsafe {
+            self.context
+                .device
+                .wait_for_fences(&wait_fences, true, u64::MAX)
+                .context("vkWaitForFences", "in flight fence")?;
+
+            self.swapchain_info.swapchain_loader.acquire_next_image(
+                self.swapchain_info.swapchain,
+                u64::MAX,
+                self.image_available_semaphores[self.current_frame],
+                vk::Fence::null(),
+            )
+        };
+        let image_index = match result {
+            // a suboptimal swapchain can still be presented to, so finish the frame and
+            // recreate afterwards
+            Ok((image_index, is_suboptimal)) => {
+                self.is_framebuffer_resized |= is_suboptimal;
+                image_index
+            }
+            // the fence hasn't been reset yet, so skipping the frame here is fine
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                self.recreate_swapchain(game)?;
+                return Ok(false);
+            }
+            Err(result) => {
+                return Err(AntithesisError::Vulkan {
+                    call: "vkAcquireNextImageKHR",
+                    resource: "swapchain image",
+                    result,
+                })
+            }
+        };
 
-            self.swapchain_info
-                .swapchain_loader
-                .acquire_next_image(
-                    self.swapchain_info.swapchain,
-                    std::u64::MAX,
-                    self.image_available_semaphores[self.current_frame],
-                    vk::Fence::null(),
-                )
-                .expect("Failed to acquire next image.")
+        // only record (and later submit) the work for the image we actually got
+        let frame_commands = &self.frame_commands[self.current_frame];
+        begin_frame_commands(
+            &self.context.device,
+            frame_commands,
+            self.gfx_pipeline.handle(),
+            self.swapchain_framebuffers[image_index as usize],
+            self.render_pass,
+            self.swapchain_info.swapchain_extent,
+            self.triangle.vertex_buffer(),
+        )?;
+
+        let mut frame = FrameContext {
+            device: &self.context.device,
+            command_buffer: frame_commands.command_buffer,
+            extent: self.swapchain_info.swapchain_extent,
+            frame_index: self.current_frame,
+            image_index,
+            is_exit_requested: false,
         };
+        game.render(&mut frame);
+        let is_exit_requested = frame.is_exit_requested;
+
+        end_frame_commands(&self.context.device, frame_commands)?;
 
         let wait_semaphores = [self.image_available_semaphores[self.current_frame]];
         let wait_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
         let signal_semaphores = [self.render_finished_semaphores[self.current_frame]];
+        let command_buffers = [frame_commands.command_buffer];
 
         let submit_infos = [*vk::SubmitInfo::builder()
             .wait_semaphores(&wait_semaphores)
-            .command_buffers(&self.command_buffers)
+            .command_buffers(&command_buffers)
             .signal_semaphores(&signal_semaphores)
             .wait_dst_stage_mask(&wait_stages)];
 
         unsafe {
-            self.device
+            self.context
+                .device
                 .reset_fences(&wait_fences)
-                .expect("Failed to reset Fence!");
+                .context("vkResetFences", "in flight fence")?;
 
-            self.device
+            self.context
+                .device
                 .queue_submit(
-                    self.graphics_queue,
+                    self.context.graphics_queue,
                     &submit_infos,
                     self.in_flight_fences[self.current_frame],
                 )
-                .expect("Failed to execute queue submit.");
+                .context("vkQueueSubmit", "frame command buffer")?;
+        }
+
+        // the image is still ours until it's presented, so this is the moment to copy it out
+        if self.is_screenshot_requested {
+            self.is_screenshot_requested = false;
+            match self.save_screenshot(image_index) {
+                Ok(path) => tracing::info!(%path, "saved screenshot"),
+                Err(err) => tracing::error!(%err, "failed to save screenshot"),
+            }
         }
 
         let swapchains = [self.swapchain_info.swapchain];
@@ -187,77 +239,200 @@ impl VulkanApp {
         let result = unsafe {
             self.swapchain_info
                 .swapchain_loader
-                .queue_present(self.present_queue, &present_info)
+                .queue_present(self.context.present_queue.unwrap(), &present_info)
         };
-        let is_resized = match result {
-            Ok(_) => self.is_framebuffer_resized,
-            Err(vk_result) => match vk_result {
-                vk::Result::ERROR_OUT_OF_DATE_KHR | vk::Result::SUBOPTIMAL_KHR => true,
-                _ => panic!("Failed to execute queue present."),
-            },
+        match result {
+            Ok(is_suboptimal) => self.is_framebuffer_resized |= is_suboptimal,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.is_framebuffer_resized = true,
+            Err(result) => {
+                return Err(AntithesisError::Vulkan {
+                    call: "vkQueuePresentKHR",
+                    resource: "swapchain image",
+                    result,
+                })
+            }
+        }
+        // if the window got minimized meanwhile, this happens once it's restored instead
+        if self.is_framebuffer_resized && !self.is_minimized() {
+            self.recreate_swapchain(game)?;
+        }
+
+        self.current_frame = (self.current_frame + 1) % self.config.frames_in_flight;
+
+        self.context.check_validation()?;
+
+        Ok(is_exit_requested)
+    }
+
+    /// Rebuilds the pipelines using shaders that changed on disk, keeping the old ones if
+    /// anything fails.
+    #[cfg(feature = "hot-reload")]
+    fn reload_shaders(&mut self, game: &mut impl Game) -> Result<()> {
+        let Some(shader_watcher) = &self.shader_watcher else {
+            return Ok(());
         };
-        if is_resized {
-            self.is_framebuffer_resized = false;
-            self.recreate_swapchain();
+        let shaders = shader_watcher.poll();
+        if shaders.is_empty() {
+            return Ok(());
+        }
+
+        // any frame in flight could still be using the pipelines about to be destroyed
+        unsafe {
+            self.context
+                .device
+                .wait_for_fences(&self.in_flight_fences, true, u64::MAX)
+                .context("vkWaitForFences", "in flight fence")?;
+        }
+
+        let mut triangle_shaders = self.triangle_shaders.clone();
+        let mut is_triangle_changed = false;
+        for (name, spirv) in TRIANGLE_SHADER_NAMES
+            .iter()
+            .zip(triangle_shaders.iter_mut())
+        {
+            if let Some(shader) = shaders.iter().find(|shader| shader.is(name)) {
+                *spirv = shader.spirv.clone();
+                is_triangle_changed = true;
+            }
+        }
+
+        if is_triangle_changed {
+            let gfx_pipeline = create_triangle_pipeline(
+                &self.context,
+                &self.pipeline_layout,
+                self.render_pass,
+                &triangle_shaders,
+            );
+            // whichever set of shaders lost out isn't needed by any pipeline anymore
+            let discarded_shaders = match gfx_pipeline {
+                Ok(gfx_pipeline) => {
+                    self.gfx_pipeline.destroy(&self.context);
+                    self.gfx_pipeline = gfx_pipeline;
+                    std::mem::replace(&mut self.triangle_shaders, triangle_shaders)
+                }
+                Err(err) => {
+                    tracing::error!(%err, "failed to rebuild the triangle pipeline");
+                    triangle_shaders
+                }
+            };
+            for spirv in discarded_shaders.iter() {
+                if !self.triangle_shaders.contains(spirv) {
+                    self.context.evict_shader_module(spirv);
+                }
+            }
         }
 
-        self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
+        game.shaders_reloaded(&self.context, self.render_pass, &shaders);
+
+        Ok(())
+    }
+
+    /// Saves the acquired swapchain image as a PNG, returning where it went.
+    fn save_screenshot(&self, image_index: u32) -> Result<String> {
+        if !self
+            .swapchain_info
+            .swapchain_usage
+            .contains(vk::ImageUsageFlags::TRANSFER_SRC)
+            || !is_readback_supported(self.swapchain_info.swapchain_format)
+        {
+            return Err(AntithesisError::UnsupportedFormat(
+                self.swapchain_info.swapchain_format,
+            ));
+        }
+
+        unsafe {
+            self.context
+                .device
+                .wait_for_fences(&[self.in_flight_fences[self.current_frame]], true, u64::MAX)
+                .context("vkWaitForFences", "in flight fence")?;
+        }
+
+        let capture = read_image(
+            &self.context,
+            self.command_pool,
+            self.swapchain_info.swapchain_images[image_index as usize],
+            self.swapchain_info.swapchain_format,
+            self.swapchain_info.swapchain_extent,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+        )?;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = format!("screenshot-{}.png", timestamp);
+        capture.save_png(&path)?;
+
+        Ok(path)
+    }
+
+    fn is_minimized(&self) -> bool {
+        let extent = window_extent(&self.window);
+        extent.width == 0 || extent.height == 0
     }
 
-    fn recreate_swapchain(&mut self) {
+    fn recreate_swapchain(&mut self, game: &mut impl Game) -> Result<()> {
+        self.is_framebuffer_resized = false;
+
+        // the old framebuffers and image views might still be in use by frames in flight
         unsafe {
-            self.device
+            self.context
+                .device
                 .device_wait_idle()
-                .expect("Failed to wait device idle!")
+                .context("vkDeviceWaitIdle", "swapchain recreation")?
         };
-        self.cleanup_swapchain();
 
         let swapchain_info = create_swapchain(
-            &self.instance,
-            &self.device,
-            &self.physical_device,
-            &self.surface_info,
-        );
-
-        self.swapchain_info = swapchain_info;
+            &self.context,
+            window_extent(&self.window),
+            self.config.present_mode,
+            self.swapchain_info.swapchain,
+        )?;
+
+        // the pipeline only depends on the extent through dynamic state, but it does need a
+        // render pass compatible with the new format
+        let is_format_changed =
+            swapchain_info.swapchain_format != self.swapchain_info.swapchain_format;
+        let render_pass = (!is_format_changed).then_some(self.render_pass);
+
+        // everything new gets built before the old is destroyed, so failing leaves the app as it
+        // was instead of holding destroyed handles
+        let mut parts = AppParts {
+            swapchain_info: Some(swapchain_info),
+            ..AppParts::default()
+        };
+        if let Err(err) =
+            parts.create_for_swapchain(&self.context, render_pass, &self.triangle_shaders)
+        {
+            parts.destroy(&self.context);
+            return Err(err);
+        }
 
-        self.render_pass = create_render_pass(&self.device, &self.swapchain_info.swapchain_format);
-        (self.pipeline_layout, self.gfx_pipeline) = create_gfx_pipeline(
-            &self.device,
-            self.render_pass,
-            &self.swapchain_info.swapchain_extent,
-        );
+        self.cleanup_swapchain();
+        self.swapchain_info = parts.swapchain_info.unwrap();
+        self.depth_target = parts.depth_target;
+        self.swapchain_framebuffers = parts.framebuffers;
+
+        if let Some(render_pass) = parts.render_pass {
+            self.cleanup_pipeline();
+            self.render_pass = render_pass;
+            (self.pipeline_layout, self.gfx_pipeline) = parts.pipeline.unwrap();
+            game.create_pipelines(&self.context, self.render_pass);
+        }
 
-        self.swapchain_framebuffers = create_framebuffers(
-            &self.device,
-            self.render_pass,
-            &self.swapchain_info.swapchain_imageviews,
-            &self.swapchain_info.swapchain_extent,
-        );
-        self.command_buffers = create_command_buffers(
-            &self.device,
-            self.command_pool,
-            self.gfx_pipeline,
-            &self.swapchain_framebuffers,
-            self.render_pass,
-            self.swapchain_info.swapchain_extent,
-            self.vertex_buffer
-        );
+        Ok(())
     }
 
-    fn cleanup_swapchain(&self) {
+    fn cleanup_swapchain(&mut self) {
+        if let Some(depth_target) = self.depth_target.take() {
+            destroy_offscreen_target(&self.context, depth_target);
+        }
         unsafe {
-            self.device
-                .free_command_buffers(self.command_pool, &self.command_buffers);
             for &framebuffer in self.swapchain_framebuffers.iter() {
-                self.device.destroy_framebuffer(framebuffer, None);
+                self.context.device.destroy_framebuffer(framebuffer, None);
             }
-            self.device.destroy_pipeline(self.gfx_pipeline, None);
-            self.device
-                .destroy_pipeline_layout(self.pipeline_layout, None);
-            self.device.destroy_render_pass(self.render_pass, None);
             for &image_view in self.swapchain_info.swapchain_imageviews.iter() {
-                self.device.destroy_image_view(image_view, None);
+                self.context.device.destroy_image_view(image_view, None);
             }
             self.swapchain_info
                 .swapchain_loader
@@ -265,71 +440,272 @@ impl VulkanApp {
         }
     }
 
-    fn run(mut self, event_loop: EventLoop<()>) {
+    fn cleanup_pipeline(&self) {
+        self.gfx_pipeline.destroy(&self.context);
+        self.pipeline_layout.destroy(&self.context);
+        unsafe {
+            self.context
+                .device
+                .destroy_render_pass(self.render_pass, None);
+        }
+    }
+
+    fn run(mut self, event_loop: EventLoop<()>, mut game: impl Game + 'static) -> ! {
+        let mut last_frame_time = Instant::now();
+
         event_loop.run(move |event, _, control_flow| match event {
-            Event::WindowEvent { event, .. } => match event {
-                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                _ => {}
-            },
-            Event::MainEventsCleared => {
-                self.window.request_redraw();
+            Event::WindowEvent { event, .. } => {
+                game.event(&event);
+
+                match event {
+                    WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                    WindowEvent::Resized(_) => self.is_framebuffer_resized = true,
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F12),
+                                ..
+                            },
+                        ..
+                    } => self.is_screenshot_requested = true,
+                    _ => {}
+                }
+            }
+            // sleep until something happens rather than spinning while minimized
+            Event::MainEventsCleared if !matches!(*control_flow, ControlFlow::ExitWithCode(_)) => {
+                if self.is_minimized() {
+                    *control_flow = ControlFlow::Wait;
+                } else {
+                    *control_flow = ControlFlow::Poll;
+                    self.window.request_redraw();
+                }
             }
             Event::RedrawRequested(_window_id) => {
-                self.draw_frame();
+                let now = Instant::now();
+                game.update(now.duration_since(last_frame_time).as_secs_f32());
+                last_frame_time = now;
+
+                match self.draw_frame(&mut game) {
+                    Ok(true) => *control_flow = ControlFlow::Exit,
+                    Ok(false) => {}
+                    Err(err) => {
+                        tracing::error!(%err, "failed to draw frame");
+                        *control_flow = ControlFlow::ExitWithCode(1);
+                    }
+                }
             }
             Event::LoopDestroyed => {
-                unsafe {
-                    self.device
-                        .device_wait_idle()
-                        .expect("Failed to wait device idle!")
-                };
+                // nothing sensible to do if this fails, we're shutting down regardless
+                let _ = unsafe { self.context.device.device_wait_idle() };
+                game.exit(&self.context);
             }
             _ => (),
         })
     }
 }
 
+/// Whatever `VulkanApp::initialize` (or `recreate_swapchain`) created so far, so a failure
+/// halfway can clean up after itself.
+#[derive(Default)]
+struct AppParts {
+    swapchain_info: Option<SwapchainInfo>,
+    depth_target: Option<OffscreenTarget>,
+    render_pass: Option<vk::RenderPass>,
+    pipeline: Option<(PipelineLayout, Pipeline)>,
+    framebuffers: Vec<vk::Framebuffer>,
+    command_pool: Option<vk::CommandPool>,
+    triangle: Option<Mesh>,
+    frame_commands: Vec<FrameCommands>,
+    sync_objects: Option<SyncObjects>,
+}
+
+impl AppParts {
+    fn create(
+        &mut self,
+        context: &VulkanContext,
+        window: &Window,
+        config: &AppConfig,
+        triangle_shaders: &[Spirv; 2],
+    ) -> Result<()> {
+        let device = &context.device;
+
+        self.swapchain_info = Some(create_swapchain(
+            context,
+            window_extent(window),
+            config.present_mode,
+            vk::SwapchainKHR::null(),
+        )?);
+
+        self.create_for_swapchain(context, None, triangle_shaders)?;
+
+        self.command_pool = Some(create_command_pool(device, &context.queue_families)?);
+
+        self.triangle = Some(create_triangle_mesh(context)?);
+
+        self.frame_commands =
+            create_frame_commands(device, &context.queue_families, config.frames_in_flight)?;
+
+        self.sync_objects = Some(create_sync_objects(device, config.frames_in_flight)?);
+
+        Ok(())
+    }
+
+    /// The depth buffer and framebuffers for `swapchain_info`, and a render pass and pipeline
+    /// for its format unless there's a compatible `render_pass` already.
+    fn create_for_swapchain(
+        &mut self,
+        context: &VulkanContext,
+        render_pass: Option<vk::RenderPass>,
+        triangle_shaders: &[Spirv; 2],
+    ) -> Result<()> {
+        let device = &context.device;
+        let swapchain_info = self
+            .swapchain_info
+            .as_ref()
+            .expect("the swapchain gets created first");
+
+        if let Some(depth_format) = context.depth_format() {
+            self.depth_target = Some(create_depth_target(
+                context,
+                depth_format,
+                swapchain_info.swapchain_extent,
+            )?);
+        }
+
+        let render_pass = match render_pass {
+            Some(render_pass) => render_pass,
+            None => {
+                let render_pass = *self.render_pass.insert(create_render_pass(
+                    device,
+                    &swapchain_info.swapchain_format,
+                    vk::ImageLayout::PRESENT_SRC_KHR,
+                    context.depth_format(),
+                )?);
+                self.pipeline = Some(create_gfx_pipeline(context, render_pass, triangle_shaders)?);
+                render_pass
+            }
+        };
+
+        self.framebuffers = create_framebuffers(
+            device,
+            render_pass,
+            &swapchain_info.swapchain_imageviews,
+            self.depth_target.as_ref().map(|depth| depth.image_view),
+            &swapchain_info.swapchain_extent,
+        )?;
+
+        Ok(())
+    }
+
+    /// In the reverse order of `create`.
+    fn destroy(self, context: &VulkanContext) {
+        let device = &context.device;
+
+        if let Some(sync_objects) = &self.sync_objects {
+            destroy_sync_objects(device, sync_objects);
+        }
+        destroy_frame_commands(device, &self.frame_commands);
+        if let Some(triangle) = self.triangle {
+            triangle.destroy(context);
+        }
+        unsafe {
+            if let Some(command_pool) = self.command_pool {
+                device.destroy_command_pool(command_pool, None);
+            }
+            for &framebuffer in self.framebuffers.iter() {
+                device.destroy_framebuffer(framebuffer, None);
+            }
+        }
+        if let Some((pipeline_layout, gfx_pipeline)) = self.pipeline {
+            gfx_pipeline.destroy(context);
+            pipeline_layout.destroy(context);
+        }
+        unsafe {
+            if let Some(render_pass) = self.render_pass {
+                device.destroy_render_pass(render_pass, None);
+            }
+        }
+        if let Some(depth_target) = self.depth_target {
+            destroy_offscreen_target(context, depth_target);
+        }
+        unsafe {
+            if let Some(swapchain_info) = self.swapchain_info {
+                for &image_view in swapchain_info.swapchain_imageviews.iter() {
+                    device.destroy_image_view(image_view, None);
+                }
+                swapchain_info
+                    .swapchain_loader
+                    .destroy_swapchain(swapchain_info.swapchain, None);
+            }
+        }
+    }
+}
+
 impl Drop for VulkanApp {
     fn drop(&mut self) {
         unsafe {
-            for i in 0..MAX_FRAMES_IN_FLIGHT {
-                self.device
+            for i in 0..self.config.frames_in_flight {
+                self.context
+                    .device
                     .destroy_semaphore(self.image_available_semaphores[i], None);
-                self.device
+                self.context
+                    .device
                     .destroy_semaphore(self.render_finished_semaphores[i], None);
-                self.device.destroy_fence(self.in_flight_fences[i], None);
+                self.context
+                    .device
+                    .destroy_fence(self.in_flight_fences[i], None);
             }
 
             self.cleanup_swapchain();
+            self.cleanup_pipeline();
 
-            self.device.destroy_command_pool(self.command_pool, None);
+            destroy_frame_commands(&self.context.device, &self.frame_commands);
+            self.context
+                .device
+                .destroy_command_pool(self.command_pool, None);
+        }
 
-            self.device.destroy_device(None);
-            self.surface_info
-                .surface_loader
-                .destroy_surface(self.surface_info.surface, None);
+        // SAFETY: the app is going away, so the triangle isn't used again
+        let triangle = unsafe { ManuallyDrop::take(&mut self.triangle) };
+        triangle.destroy(&self.context);
 
-            self.instance.destroy_instance(None);
-        }
+        // the context tears down the device, surface and instance when it drops
     }
 }
 
-pub fn run_app() {
-    let (event_loop, window) = create_window(1280, 720, "Antithesis");
+/// Opens the window described by `config` and runs `game` in the frame loop; only returns if
+/// setting up the engine failed.
+pub fn run_app(config: AppConfig, mut game: impl Game + 'static) -> Result<()> {
+    let (event_loop, window) = create_window(&config)?;
 
-    let app = VulkanApp::initialize(window);
-    app.run(event_loop);
+    let app = VulkanApp::initialize(window, config)?;
+    game.init(&app.context);
+    game.create_pipelines(&app.context, app.render_pass);
+
+    app.run(event_loop, game);
 }
 
-fn create_window(width: u32, height: u32, title: &str) -> (EventLoop<()>, Window) {
+/// The window's drawable size in physical pixels, which is what the swapchain has to match.
+fn window_extent(window: &Window) -> vk::Extent2D {
+    let size = window.inner_size();
+    vk::Extent2D {
+        width: size.width,
+        height: size.height,
+    }
+}
+
+fn create_window(config: &AppConfig) -> Result<(EventLoop<()>, Window)> {
     let event_loop = EventLoop::new();
+    let fullscreen = config.is_fullscreen.then_some(Fullscreen::Borderless(None));
     let window = WindowBuilder::new()
-        .with_title(title)
-        .with_inner_size(winit::dpi::LogicalSize::new(width, height))
-        .build(&event_loop)
-        .unwrap();
+        .with_title(&config.title)
+        .with_inner_size(winit::dpi::LogicalSize::new(config.width, config.height))
+        .with_resizable(config.is_resizable)
+        .with_fullscreen(fullscreen)
+        .build(&event_loop)?;
 
-    (event_loop, window)
+    Ok((event_loop, window))
 }
 
 pub struct SurfaceInfo {
@@ -338,64 +714,24 @@ pub struct SurfaceInfo {
 }
 
 impl SurfaceInfo {
-    pub fn create(window: &Window, entry: &Entry, instance: &Instance) -> Self {
+    pub fn create(window: &Window, entry: &Entry, instance: &Instance) -> Result<Self> {
         let surface = unsafe {
             ash_window::create_surface(
-                &entry,
-                &instance,
+                entry,
+                instance,
                 window.raw_display_handle(),
                 window.raw_window_handle(),
                 None,
             )
-            .unwrap()
+            .context("vkCreateSurfaceKHR", "window surface")?
         };
 
         // What is this actually? How is this different from the surfaceKHR above?
-        let surface_loader = Surface::new(&entry, &instance);
+        let surface_loader = Surface::new(entry, instance);
 
-        SurfaceInfo {
+        Ok(SurfaceInfo {
             surface,
             surface_loader,
-        }
-    }
-}
-
-fn create_instance(window: &Window, entry: &Entry) -> Instance {
-    let app_name = CStr::from_bytes_with_nul(b"Demo\0").unwrap();
-    let engine_name = CStr::from_bytes_with_nul(b"Antithesis\0").unwrap();
-    let app_info = ApplicationInfo::builder()
-        .application_name(app_name)
-        .application_version(1)
-        .engine_name(engine_name)
-        .engine_version(1)
-        .api_version(vk::make_api_version(0, 1, 0, 0));
-
-    let layer_names = [CStr::from_bytes_with_nul(b"VK_LAYER_KHRONOS_validation\0").unwrap()];
-    let layers_names_raw: Vec<*const c_char> = layer_names
-        .iter()
-        .map(|raw_name| raw_name.as_ptr())
-        .collect();
-
-    // required extensions to support the passed window
-    let extension_names = ash_window::enumerate_required_extensions(window.raw_display_handle())
-        .unwrap()
-        .to_vec();
-
-    let create_flags = if cfg!(any(target_os = "macos", target_os = "ios")) {
-        vk::InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR
-    } else {
-        vk::InstanceCreateFlags::default()
-    };
-
-    let create_info = vk::InstanceCreateInfo::builder()
-        .application_info(&app_info)
-        .enabled_layer_names(&layers_names_raw)
-        .enabled_extension_names(&extension_names)
-        .flags(create_flags);
-
-    unsafe {
-        return entry
-            .create_instance(&create_info, None)
-            .expect("Instance creation error");
+        })
     }
 }
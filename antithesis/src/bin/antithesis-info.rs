@@ -0,0 +1,72 @@
+//! Prints what Vulkan reports about this machine, for attaching to rendering bug reports.
+//!
+//! Usage: `antithesis-info [--json] [--headless]`
+//!
+//! `--headless` skips creating a (hidden) window, so surface formats and present modes aren't
+//! listed; that also happens automatically when there's no display to open one on.
+
+use std::process::ExitCode;
+
+use antithesis::info::SystemInfo;
+use winit::{event_loop::EventLoop, window::WindowBuilder};
+
+fn main() -> ExitCode {
+    let mut is_json = false;
+    let mut is_headless = false;
+
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => is_json = true,
+            "--headless" => is_headless = true,
+            "-h" | "--help" => {
+                println!("Usage: antithesis-info [--json] [--headless]");
+                return ExitCode::SUCCESS;
+            }
+            _ => {
+                eprintln!("Unknown argument {}, see --help", arg);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    // winit panics rather than erroring when there's no display server to connect to
+    let event_loop = (!is_headless && has_display()).then(EventLoop::new);
+    let window = event_loop.as_ref().and_then(|event_loop| {
+        WindowBuilder::new()
+            .with_title("antithesis-info")
+            .with_visible(false)
+            .build(event_loop)
+            .map_err(|err| eprintln!("Couldn't open a window, skipping surface info: {}", err))
+            .ok()
+    });
+
+    let info = match SystemInfo::collect(window.as_ref()) {
+        Ok(info) => info,
+        Err(err) => {
+            eprintln!("Failed to query Vulkan: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if is_json {
+        match serde_json::to_string_pretty(&info) {
+            Ok(json) => println!("{}", json),
+            Err(err) => {
+                eprintln!("Failed to serialize to JSON: {}", err);
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        print!("{}", info);
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn has_display() -> bool {
+    if cfg!(all(unix, not(any(target_os = "macos", target_os = "ios")))) {
+        std::env::var_os("DISPLAY").is_some() || std::env::var_os("WAYLAND_DISPLAY").is_some()
+    } else {
+        true
+    }
+}
@@ -0,0 +1,195 @@
+#[cfg(feature = "hot-reload")]
+use std::path::PathBuf;
+
+use ash::vk;
+
+use crate::features::DeviceFeature;
+
+/// Startup options for the engine, passed to `run_app` (or the headless `Renderer`).
+///
+/// ```no_run
+/// use antithesis::config::AppConfig;
+///
+/// let config = AppConfig::new()
+///     .title("Croak")
+///     .size(1920, 1080)
+///     .app_name("Croak")
+///     .app_version(0, 3, 1)
+///     .validation(false);
+/// ```
+#[derive(Debug, Clone)]
+pub struct AppConfig {
+    pub(crate) title: String,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) is_resizable: bool,
+    pub(crate) is_fullscreen: bool,
+
+    pub(crate) app_name: String,
+    pub(crate) app_version: u32,
+    pub(crate) api_version: u32,
+    pub(crate) is_validation_enabled: bool,
+    pub(crate) is_validation_strict: bool,
+    pub(crate) device_selector: Option<String>,
+    pub(crate) required_extensions: Vec<String>,
+    pub(crate) optional_extensions: Vec<String>,
+    pub(crate) required_features: Vec<DeviceFeature>,
+    pub(crate) optional_features: Vec<DeviceFeature>,
+
+    pub(crate) present_mode: vk::PresentModeKHR,
+    pub(crate) frames_in_flight: usize,
+    pub(crate) has_depth_buffer: bool,
+
+    #[cfg(feature = "hot-reload")]
+    pub(crate) shader_dir: Option<PathBuf>,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        AppConfig {
+            title: "Antithesis".to_owned(),
+            width: 1280,
+            height: 720,
+            is_resizable: true,
+            is_fullscreen: false,
+            app_name: "Demo".to_owned(),
+            app_version: vk::make_api_version(0, 0, 0, 1),
+            api_version: vk::API_VERSION_1_0,
+            is_validation_enabled: cfg!(debug_assertions),
+            is_validation_strict: false,
+            device_selector: None,
+            required_extensions: vec![],
+            optional_extensions: vec![],
+            required_features: vec![],
+            optional_features: vec![],
+            // "Triple buffering" mailbox mode if possible
+            present_mode: vk::PresentModeKHR::MAILBOX,
+            frames_in_flight: 2,
+            has_depth_buffer: false,
+            #[cfg(feature = "hot-reload")]
+            shader_dir: None,
+        }
+    }
+}
+
+impl AppConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Window title.
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    /// Window size in logical pixels; the headless renderer uses it as its image size.
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn resizable(mut self, is_resizable: bool) -> Self {
+        self.is_resizable = is_resizable;
+        self
+    }
+
+    /// Borderless fullscreen on the current monitor.
+    pub fn fullscreen(mut self, is_fullscreen: bool) -> Self {
+        self.is_fullscreen = is_fullscreen;
+        self
+    }
+
+    /// Application name reported to the driver.
+    pub fn app_name(mut self, app_name: impl Into<String>) -> Self {
+        self.app_name = app_name.into();
+        self
+    }
+
+    /// Application version reported to the driver.
+    pub fn app_version(mut self, major: u32, minor: u32, patch: u32) -> Self {
+        self.app_version = vk::make_api_version(0, major, minor, patch);
+        self
+    }
+
+    /// Highest Vulkan version the engine may use, 1.0 by default.
+    pub fn api_version(mut self, major: u32, minor: u32) -> Self {
+        self.api_version = vk::make_api_version(0, major, minor, 0);
+        self
+    }
+
+    /// Enables the Khronos validation layer, on by default in debug builds. Skipped with a
+    /// warning where the layer isn't installed.
+    pub fn validation(mut self, is_validation_enabled: bool) -> Self {
+        self.is_validation_enabled = is_validation_enabled;
+        self
+    }
+
+    /// Fails the frame with `AntithesisError::Validation` if the validation layer reported any
+    /// errors while it was recorded or submitted, meant for tests.
+    pub fn strict_validation(mut self, is_validation_strict: bool) -> Self {
+        self.is_validation_strict = is_validation_strict;
+        self
+    }
+
+    /// Forces a GPU by index, UUID or (part of its) name instead of picking the best one.
+    /// The `ANTITHESIS_DEVICE` environment variable overrides this.
+    pub fn device(mut self, selector: impl Into<String>) -> Self {
+        self.device_selector = Some(selector.into());
+        self
+    }
+
+    /// Device extension the game can't run without; GPUs missing it aren't considered.
+    /// The swapchain extension is always required when there's a window.
+    pub fn require_extension(mut self, name: impl Into<String>) -> Self {
+        self.required_extensions.push(name.into());
+        self
+    }
+
+    /// Device extension to enable where supported, see `VulkanContext::is_extension_enabled`.
+    pub fn optional_extension(mut self, name: impl Into<String>) -> Self {
+        self.optional_extensions.push(name.into());
+        self
+    }
+
+    /// Device feature the game can't run without; GPUs missing it aren't considered.
+    pub fn require_feature(mut self, feature: DeviceFeature) -> Self {
+        self.required_features.push(feature);
+        self
+    }
+
+    /// Device feature to enable where supported, see `VulkanContext::is_feature_enabled`.
+    pub fn optional_feature(mut self, feature: DeviceFeature) -> Self {
+        self.optional_features.push(feature);
+        self
+    }
+
+    /// Present mode to use when the surface supports it, falling back to FIFO otherwise.
+    pub fn present_mode(mut self, present_mode: vk::PresentModeKHR) -> Self {
+        self.present_mode = present_mode;
+        self
+    }
+
+    /// How many frames the CPU may record ahead of the GPU, at least 1.
+    pub fn frames_in_flight(mut self, frames_in_flight: usize) -> Self {
+        self.frames_in_flight = frames_in_flight.max(1);
+        self
+    }
+
+    /// Gives the render pass games draw in a depth attachment, cleared to 1.0 every frame, for
+    /// pipelines built with `GraphicsPipelineBuilder::depth`.
+    pub fn depth_buffer(mut self, has_depth_buffer: bool) -> Self {
+        self.has_depth_buffer = has_depth_buffer;
+        self
+    }
+
+    /// Watches `dir` for changed shaders while the app runs, rebuilding the pipelines using
+    /// them between frames; see `Game::shaders_reloaded`. Usually
+    /// `concat!(env!("CARGO_MANIFEST_DIR"), "/shaders")`.
+    #[cfg(feature = "hot-reload")]
+    pub fn hot_reload_shaders(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.shader_dir = Some(dir.into());
+        self
+    }
+}
@@ -0,0 +1,411 @@
+use std::{
+    borrow::Cow,
+    ffi::{CStr, CString},
+    os::raw::c_char,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use ash::{
+    extensions::ext::DebugUtils,
+    vk::{self, ApplicationInfo},
+    Entry, Instance,
+};
+use raw_window_handle::HasRawDisplayHandle;
+use winit::window::Window;
+
+use crate::{
+    allocator::{Allocator, DeviceMemoryBackend},
+    app::SurfaceInfo,
+    config::AppConfig,
+    debug::DebugMessenger,
+    device::{create_logical_device, pick_physical_device, vk_to_string, QueueFamilyIndices},
+    error::{AntithesisError, Result, VkResultExt},
+    features::{DeviceFeature, EnabledDeviceFeatures},
+    offscreen::find_depth_format,
+    shader::{max_spirv_version, ShaderCache, Spirv},
+};
+
+/// The instance, device and queues shared by the windowed app and the headless renderer.
+pub struct VulkanContext {
+    // the instance is created from the entry, so keep it around until everything is destroyed
+    _entry: Entry,
+    pub(crate) instance: Instance,
+    debug_messenger: Option<DebugMessenger>,
+    is_validation_strict: bool,
+    pub(crate) surface_info: Option<SurfaceInfo>,
+
+    pub(crate) physical_device: vk::PhysicalDevice,
+    pub(crate) device: ash::Device, // Logical device
+    pub(crate) queue_families: QueueFamilyIndices,
+    pub(crate) enabled_features: EnabledDeviceFeatures,
+    // the lower of what the app asked for and what the device supports
+    api_version: u32,
+    frames_in_flight: usize,
+    depth_format: Option<vk::Format>,
+    allocator: Mutex<Allocator<DeviceMemoryBackend>>,
+    shader_cache: Mutex<ShaderCache>,
+
+    pub(crate) graphics_queue: vk::Queue,
+    pub(crate) present_queue: Option<vk::Queue>,
+    // the graphics queue again when the device has no separate family for these
+    pub(crate) transfer_queue: vk::Queue,
+    pub(crate) compute_queue: vk::Queue,
+}
+
+impl VulkanContext {
+    /// Creates a context that can present to `window`, or a headless one when there is no window.
+    pub(crate) fn new(window: Option<&Window>, config: &AppConfig) -> Result<Self> {
+        // Load vulkan at runtime, so machines without a loader fail here instead of at link time
+        let entry = unsafe { ash::Entry::load()? };
+
+        // Make instance
+        let (instance, is_validation_enabled) = create_instance(window, &entry, config)?;
+
+        let debug_messenger = if is_validation_enabled {
+            match DebugMessenger::create(&entry, &instance) {
+                Ok(debug_messenger) => Some(debug_messenger),
+                Err(err) => {
+                    unsafe { instance.destroy_instance(None) };
+                    return Err(err);
+                }
+            }
+        } else {
+            None
+        };
+
+        // Create surface and other surface thing
+        let surface_info = match window.map(|window| SurfaceInfo::create(window, &entry, &instance))
+        {
+            Some(Ok(surface_info)) => Some(surface_info),
+            Some(Err(err)) => {
+                if let Some(debug_messenger) = &debug_messenger {
+                    debug_messenger.destroy();
+                }
+                unsafe { instance.destroy_instance(None) };
+                return Err(err);
+            }
+            None => None,
+        };
+
+        // Get physical device, logical device, and gfx queue
+        let device_and_queues = pick_physical_device(&instance, surface_info.as_ref(), config)
+            .and_then(|physical_device| {
+                create_logical_device(&instance, &physical_device, surface_info.as_ref(), config)
+                    .map(|(device, queue_families, enabled_features)| {
+                        (physical_device, device, queue_families, enabled_features)
+                    })
+            });
+
+        let (physical_device, device, queue_families, enabled_features) = match device_and_queues {
+            Ok(device_and_queues) => device_and_queues,
+            Err(err) => {
+                unsafe {
+                    if let Some(surface_info) = &surface_info {
+                        surface_info
+                            .surface_loader
+                            .destroy_surface(surface_info.surface, None);
+                    }
+                    if let Some(debug_messenger) = &debug_messenger {
+                        debug_messenger.destroy();
+                    }
+                    instance.destroy_instance(None);
+                }
+                return Err(err);
+            }
+        };
+
+        let graphics_family = queue_families.graphics_family.unwrap();
+        let graphics_queue = unsafe { device.get_device_queue(graphics_family, 0) };
+        let present_queue = queue_families
+            .present_family
+            .map(|present_family| unsafe { device.get_device_queue(present_family, 0) });
+        let transfer_queue = unsafe {
+            device.get_device_queue(queue_families.transfer_family.unwrap_or(graphics_family), 0)
+        };
+        let compute_queue = unsafe {
+            device.get_device_queue(queue_families.compute_family.unwrap_or(graphics_family), 0)
+        };
+        let memory_properties =
+            unsafe { instance.get_physical_device_memory_properties(physical_device) };
+        let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+        let allocator = Allocator::new(
+            DeviceMemoryBackend::new(device.clone()),
+            memory_properties,
+            properties.limits.buffer_image_granularity,
+        );
+
+        tracing::debug!(
+            graphics_family,
+            present_family = ?queue_families.present_family,
+            transfer_family = ?queue_families.transfer_family,
+            compute_family = ?queue_families.compute_family,
+            "created queues"
+        );
+
+        let depth_format = config
+            .has_depth_buffer
+            .then(|| find_depth_format(&instance, physical_device));
+
+        Ok(VulkanContext {
+            _entry: entry,
+            instance,
+            debug_messenger,
+            is_validation_strict: config.is_validation_strict,
+            surface_info,
+            physical_device,
+            device,
+            queue_families,
+            enabled_features,
+            api_version: config.api_version.min(properties.api_version),
+            frames_in_flight: config.frames_in_flight,
+            depth_format,
+            allocator: Mutex::new(allocator),
+            shader_cache: Mutex::default(),
+            graphics_queue,
+            present_queue,
+            transfer_queue,
+            compute_queue,
+        })
+    }
+
+    pub(crate) fn surface_info(&self) -> &SurfaceInfo {
+        self.surface_info
+            .as_ref()
+            .expect("Context was created without a surface!")
+    }
+
+    /// Where all buffer and image memory comes from.
+    pub(crate) fn allocator(&self) -> MutexGuard<'_, Allocator<DeviceMemoryBackend>> {
+        // the allocator doesn't panic halfway through changing its state, so it's still usable
+        self.allocator
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// A shader module for `spirv`, shared with every earlier call for the same code. Modules
+    /// stay around until the context is dropped or they're evicted.
+    pub fn shader_module(&self, spirv: &Spirv) -> Result<vk::ShaderModule> {
+        let max = max_spirv_version(self.api_version);
+        if spirv.version() > max {
+            return Err(AntithesisError::UnsupportedSpirvVersion {
+                version: spirv.version(),
+                max,
+            });
+        }
+
+        self.shader_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_or_create(&self.device, spirv)
+    }
+
+    /// Destroys the module `shader_module` returned for `spirv`, like after a hot reload
+    /// replaced the code. Pipelines built from it keep working, but the handle mustn't be used
+    /// again; asking for `spirv` later creates a new module.
+    pub fn evict_shader_module(&self, spirv: &Spirv) {
+        self.shader_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.device, spirv);
+    }
+
+    /// Whether `name` got enabled, either required or optional and supported.
+    pub fn is_extension_enabled(&self, name: &str) -> bool {
+        self.enabled_features
+            .extensions
+            .iter()
+            .any(|extension| extension == name)
+    }
+
+    /// Whether `feature` got enabled, either required or optional and supported.
+    pub fn is_feature_enabled(&self, feature: DeviceFeature) -> bool {
+        self.enabled_features.features.contains(&feature)
+    }
+
+    /// All the device extensions and features that got enabled.
+    pub fn enabled_features(&self) -> &EnabledDeviceFeatures {
+        &self.enabled_features
+    }
+
+    /// How many frames can be recorded while earlier ones are still rendering, which is how
+    /// many copies per-frame resources like uniform buffers need.
+    pub fn frames_in_flight(&self) -> usize {
+        self.frames_in_flight
+    }
+
+    /// The format of the depth attachment in the render pass games draw in, if
+    /// `AppConfig::depth_buffer` asked for one.
+    pub fn depth_format(&self) -> Option<vk::Format> {
+        self.depth_format
+    }
+
+    /// Queue for uploads that can run alongside rendering, on a dedicated transfer family if
+    /// the device has one.
+    pub fn transfer_queue(&self) -> vk::Queue {
+        self.transfer_queue
+    }
+
+    /// The family `transfer_queue` belongs to.
+    pub fn transfer_family(&self) -> u32 {
+        let queue_families = &self.queue_families;
+        queue_families
+            .transfer_family
+            .or(queue_families.graphics_family)
+            .unwrap()
+    }
+
+    /// Queue for async compute, on a family without graphics if the device has one.
+    pub fn compute_queue(&self) -> vk::Queue {
+        self.compute_queue
+    }
+
+    /// The family `compute_queue` belongs to.
+    pub fn compute_family(&self) -> u32 {
+        let queue_families = &self.queue_families;
+        queue_families
+            .compute_family
+            .or(queue_families.graphics_family)
+            .unwrap()
+    }
+
+    /// With strict validation on, errors if the validation layer complained since the last check.
+    pub(crate) fn check_validation(&self) -> Result<()> {
+        match &self.debug_messenger {
+            Some(debug_messenger) if self.is_validation_strict => debug_messenger.check_errors(),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for VulkanContext {
+    fn drop(&mut self) {
+        self.allocator
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .free_all();
+        self.shader_cache
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .destroy_all(&self.device);
+
+        unsafe {
+            self.device.destroy_device(None);
+            if let Some(surface_info) = &self.surface_info {
+                surface_info
+                    .surface_loader
+                    .destroy_surface(surface_info.surface, None);
+            }
+            if let Some(debug_messenger) = &self.debug_messenger {
+                debug_messenger.destroy();
+            }
+
+            self.instance.destroy_instance(None);
+        }
+    }
+}
+
+const VALIDATION_LAYER: &CStr = c"VK_LAYER_KHRONOS_validation";
+
+/// Creates the instance with whatever optional layers and extensions are actually installed,
+/// returning whether validation ended up enabled.
+pub(crate) fn create_instance(
+    window: Option<&Window>,
+    entry: &Entry,
+    config: &AppConfig,
+) -> Result<(Instance, bool)> {
+    // a name with a nul in it can't be handed to vulkan, it's only informational anyway
+    let app_name = CString::new(config.app_name.as_str()).unwrap_or_default();
+    let engine_name = c"Antithesis";
+    let app_info = ApplicationInfo::builder()
+        .application_name(&app_name)
+        .application_version(config.app_version)
+        .engine_name(engine_name)
+        .engine_version(1)
+        .api_version(config.api_version);
+
+    // ask first, so a missing SDK doesn't make instance creation fail outright
+    let available_layers: Vec<String> = entry
+        .enumerate_instance_layer_properties()
+        .context("vkEnumerateInstanceLayerProperties", "instance")?
+        .iter()
+        .map(|layer| vk_to_string(&layer.layer_name))
+        .collect();
+    let available_extensions: Vec<String> = entry
+        .enumerate_instance_extension_properties(None)
+        .context("vkEnumerateInstanceExtensionProperties", "instance")?
+        .iter()
+        .map(|extension| vk_to_string(&extension.extension_name))
+        .collect();
+    let is_available = |name: &CStr, available: &[String]| {
+        available
+            .iter()
+            .any(|available| name.to_str() == Ok(available))
+    };
+
+    // required extensions to support the passed window, headless rendering needs none
+    let mut extension_names = match window {
+        Some(window) => ash_window::enumerate_required_extensions(window.raw_display_handle())
+            .context("vkEnumerateInstanceExtensionProperties", "window surface")?
+            .to_vec(),
+        None => vec![],
+    };
+
+    // validation needs both the layer and debug utils to get its messages into tracing
+    let mut layer_names = vec![];
+    let is_validation_enabled = if !config.is_validation_enabled {
+        false
+    } else if !is_available(VALIDATION_LAYER, &available_layers) {
+        tracing::warn!(
+            layer = ?VALIDATION_LAYER,
+            "validation was requested but the layer isn't installed, skipping it"
+        );
+        false
+    } else if !is_available(DebugUtils::name(), &available_extensions) {
+        tracing::warn!(
+            extension = ?DebugUtils::name(),
+            "validation was requested but debug utils isn't available, skipping it"
+        );
+        false
+    } else {
+        layer_names.push(VALIDATION_LAYER.as_ptr());
+        extension_names.push(DebugUtils::name().as_ptr());
+        true
+    };
+
+    // moltenvk only shows up when asking for portability devices
+    let portability_enumeration = vk::KhrPortabilityEnumerationFn::name();
+    let create_flags = if cfg!(any(target_os = "macos", target_os = "ios"))
+        && is_available(portability_enumeration, &available_extensions)
+    {
+        extension_names.push(portability_enumeration.as_ptr());
+        vk::InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR
+    } else {
+        vk::InstanceCreateFlags::default()
+    };
+
+    let report = |names: &[*const c_char]| -> Vec<Cow<str>> {
+        names
+            .iter()
+            .map(|&name| unsafe { CStr::from_ptr(name) }.to_string_lossy())
+            .collect()
+    };
+    tracing::info!(
+        layers = ?report(&layer_names),
+        extensions = ?report(&extension_names),
+        "creating instance"
+    );
+
+    let create_info = vk::InstanceCreateInfo::builder()
+        .application_info(&app_info)
+        .enabled_layer_names(&layer_names)
+        .enabled_extension_names(&extension_names)
+        .flags(create_flags);
+
+    let instance = unsafe {
+        entry
+            .create_instance(&create_info, None)
+            .context("vkCreateInstance", "instance")?
+    };
+
+    Ok((instance, is_validation_enabled))
+}
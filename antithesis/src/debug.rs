@@ -0,0 +1,108 @@
+use std::{
+    borrow::Cow,
+    ffi::{c_void, CStr},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use ash::{extensions::ext::DebugUtils, vk, Entry, Instance};
+
+use crate::error::{AntithesisError, Result, VkResultExt};
+
+/// Forwards validation layer messages into `tracing`, counting errors along the way.
+pub struct DebugMessenger {
+    debug_utils_loader: DebugUtils,
+    messenger: vk::DebugUtilsMessengerEXT,
+    // boxed so the callback's pointer to it stays valid however the messenger is moved around
+    error_count: Box<AtomicUsize>,
+}
+
+impl DebugMessenger {
+    pub fn create(entry: &Entry, instance: &Instance) -> Result<Self> {
+        let debug_utils_loader = DebugUtils::new(entry, instance);
+        let error_count = Box::new(AtomicUsize::new(0));
+
+        let create_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
+            .message_severity(
+                vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
+                    | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+                    | vk::DebugUtilsMessageSeverityFlagsEXT::INFO
+                    | vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE,
+            )
+            .message_type(
+                vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                    | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+                    | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+            )
+            .pfn_user_callback(Some(vulkan_debug_callback))
+            .user_data(&*error_count as *const AtomicUsize as *mut c_void);
+
+        let messenger = unsafe {
+            debug_utils_loader
+                .create_debug_utils_messenger(&create_info, None)
+                .context("vkCreateDebugUtilsMessengerEXT", "debug messenger")?
+        };
+
+        Ok(DebugMessenger {
+            debug_utils_loader,
+            messenger,
+            error_count,
+        })
+    }
+
+    /// Errors if the validation layer reported anything since the last check.
+    pub fn check_errors(&self) -> Result<()> {
+        match self.error_count.swap(0, Ordering::Relaxed) {
+            0 => Ok(()),
+            count => Err(AntithesisError::Validation(count)),
+        }
+    }
+
+    /// Has to happen before the instance is destroyed.
+    pub fn destroy(&self) {
+        unsafe {
+            self.debug_utils_loader
+                .destroy_debug_utils_messenger(self.messenger, None);
+        }
+    }
+}
+
+unsafe extern "system" fn vulkan_debug_callback(
+    message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    message_types: vk::DebugUtilsMessageTypeFlagsEXT,
+    p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
+    p_user_data: *mut c_void,
+) -> vk::Bool32 {
+    let callback_data = &*p_callback_data;
+    let message_id = lossy_c_str(callback_data.p_message_id_name);
+    let message = lossy_c_str(callback_data.p_message);
+
+    match message_severity {
+        vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => {
+            if let Some(error_count) = (p_user_data as *const AtomicUsize).as_ref() {
+                error_count.fetch_add(1, Ordering::Relaxed);
+            }
+            tracing::error!(target: "antithesis::vulkan", ?message_types, %message_id, "{}", message)
+        }
+        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => {
+            tracing::warn!(target: "antithesis::vulkan", ?message_types, %message_id, "{}", message)
+        }
+        // the loader and layers are chatty at info level, so keep that out of the default output
+        vk::DebugUtilsMessageSeverityFlagsEXT::INFO => {
+            tracing::debug!(target: "antithesis::vulkan", ?message_types, %message_id, "{}", message)
+        }
+        _ => {
+            tracing::trace!(target: "antithesis::vulkan", ?message_types, %message_id, "{}", message)
+        }
+    }
+
+    // returning true would abort the call that triggered the message
+    vk::FALSE
+}
+
+unsafe fn lossy_c_str<'a>(ptr: *const std::os::raw::c_char) -> Cow<'a, str> {
+    if ptr.is_null() {
+        Cow::Borrowed("")
+    } else {
+        CStr::from_ptr(ptr).to_string_lossy()
+    }
+}
@@ -0,0 +1,575 @@
+use std::marker::PhantomData;
+
+use ash::vk;
+
+use crate::{
+    allocator::{Allocation, MemoryLocation},
+    context::VulkanContext,
+    error::{AntithesisError, Result, VkResultExt},
+    pipeline::{create_buffer, destroy_buffer},
+    pod::{bytes_of, Pod},
+};
+
+// pools start small and double each time one runs out, up to this
+const INITIAL_SETS_PER_POOL: u32 = 64;
+const MAX_SETS_PER_POOL: u32 = 4096;
+
+/// Descriptors of each type a pool has room for, per set it can hold; a guess at what a typical
+/// set needs, so pools rarely run out of one type before they're out of sets.
+const DEFAULT_POOL_RATIOS: [(vk::DescriptorType, f32); 7] = [
+    (vk::DescriptorType::UNIFORM_BUFFER, 2.0),
+    (vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, 1.0),
+    (vk::DescriptorType::STORAGE_BUFFER, 1.0),
+    (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 4.0),
+    (vk::DescriptorType::SAMPLED_IMAGE, 1.0),
+    (vk::DescriptorType::STORAGE_IMAGE, 1.0),
+    (vk::DescriptorType::SAMPLER, 1.0),
+];
+
+/// One binding in a `DescriptorSetLayout`, matching a `layout(binding = n)` in the shaders.
+#[derive(Debug, Clone, Copy)]
+pub struct DescriptorBinding {
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    /// More than one makes it an array in the shader.
+    pub count: u32,
+    pub stages: vk::ShaderStageFlags,
+}
+
+impl DescriptorBinding {
+    pub fn uniform_buffer(binding: u32, stages: vk::ShaderStageFlags) -> Self {
+        DescriptorBinding {
+            binding,
+            descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+            count: 1,
+            stages,
+        }
+    }
+
+    pub fn storage_buffer(binding: u32, stages: vk::ShaderStageFlags) -> Self {
+        DescriptorBinding {
+            binding,
+            descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+            count: 1,
+            stages,
+        }
+    }
+
+    pub fn combined_image_sampler(binding: u32, stages: vk::ShaderStageFlags) -> Self {
+        DescriptorBinding {
+            binding,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            count: 1,
+            stages,
+        }
+    }
+}
+
+/// What goes in a descriptor set, used to allocate sets and to build pipeline layouts.
+pub struct DescriptorSetLayout {
+    layout: vk::DescriptorSetLayout,
+    bindings: Vec<DescriptorBinding>,
+}
+
+impl DescriptorSetLayout {
+    pub fn new(context: &VulkanContext, bindings: &[DescriptorBinding]) -> Result<Self> {
+        let layout_bindings: Vec<_> = bindings
+            .iter()
+            .map(|binding| {
+                *vk::DescriptorSetLayoutBinding::builder()
+                    .binding(binding.binding)
+                    .descriptor_type(binding.descriptor_type)
+                    .descriptor_count(binding.count)
+                    .stage_flags(binding.stages)
+            })
+            .collect();
+
+        let create_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&layout_bindings);
+
+        let layout = unsafe {
+            context
+                .device
+                .create_descriptor_set_layout(&create_info, None)
+                .context("vkCreateDescriptorSetLayout", "descriptor set layout")?
+        };
+
+        Ok(DescriptorSetLayout {
+            layout,
+            bindings: bindings.to_vec(),
+        })
+    }
+
+    pub fn handle(&self) -> vk::DescriptorSetLayout {
+        self.layout
+    }
+
+    pub fn bindings(&self) -> &[DescriptorBinding] {
+        &self.bindings
+    }
+
+    /// Pipeline layouts and sets made from it can outlive it, so this can happen any time after
+    /// they're created.
+    pub fn destroy(&self, context: &VulkanContext) {
+        unsafe {
+            context
+                .device
+                .destroy_descriptor_set_layout(self.layout, None)
+        };
+    }
+}
+
+/// Hands out descriptor sets from a growing list of pools, so nothing has to know up front how
+/// many sets will be needed.
+pub struct DescriptorAllocator {
+    ratios: Vec<(vk::DescriptorType, f32)>,
+    sets_per_pool: u32,
+    // pools with (maybe) some room left, the last one gets used first
+    ready_pools: Vec<DescriptorPool>,
+    full_pools: Vec<DescriptorPool>,
+}
+
+struct DescriptorPool {
+    pool: vk::DescriptorPool,
+    capacity: PoolCapacity,
+    // counted here because running out is undefined behaviour on Vulkan 1.0 without
+    // VK_KHR_maintenance1, instead of an ERROR_OUT_OF_POOL_MEMORY to react to
+    left: PoolCapacity,
+}
+
+/// Sets and descriptors of each type a pool has room for.
+#[derive(Debug, Clone)]
+struct PoolCapacity {
+    sets: u32,
+    descriptors: Vec<vk::DescriptorPoolSize>,
+}
+
+impl PoolCapacity {
+    /// Takes the room for one set with `bindings`, or leaves everything as it was and returns
+    /// false if it doesn't fit.
+    fn take(&mut self, bindings: &[DescriptorBinding]) -> bool {
+        if self.sets == 0 {
+            return false;
+        }
+
+        let mut descriptors = self.descriptors.clone();
+        for binding in bindings {
+            match descriptors
+                .iter_mut()
+                .find(|pool_size| pool_size.ty == binding.descriptor_type)
+            {
+                Some(pool_size) if pool_size.descriptor_count >= binding.count => {
+                    pool_size.descriptor_count -= binding.count;
+                }
+                _ => return false,
+            }
+        }
+
+        self.sets -= 1;
+        self.descriptors = descriptors;
+        true
+    }
+}
+
+impl Default for DescriptorAllocator {
+    fn default() -> Self {
+        DescriptorAllocator {
+            ratios: DEFAULT_POOL_RATIOS.to_vec(),
+            sets_per_pool: INITIAL_SETS_PER_POOL,
+            ready_pools: vec![],
+            full_pools: vec![],
+        }
+    }
+}
+
+impl DescriptorAllocator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allocates a set with `layout`, making a new pool when the current ones are full.
+    pub fn allocate(
+        &mut self,
+        context: &VulkanContext,
+        layout: &DescriptorSetLayout,
+    ) -> Result<vk::DescriptorSet> {
+        let device = &context.device;
+        let bindings = layout.bindings();
+
+        let has_room = self
+            .ready_pools
+            .last_mut()
+            .is_some_and(|pool| pool.left.take(bindings));
+        if !has_room {
+            self.full_pools.extend(self.ready_pools.pop());
+
+            let mut pool = self.create_pool(device, layout)?;
+            let has_room = pool.left.take(bindings);
+            debug_assert!(
+                has_room,
+                "pools are made with room for one set of the layout"
+            );
+            self.ready_pools.push(pool);
+        }
+
+        let pool = self
+            .ready_pools
+            .last()
+            .expect("a pool with room was just made sure of");
+        allocate_set(device, pool.pool, layout)
+    }
+
+    /// Frees every set allocated so far at once, keeping the pools around for reuse. None of
+    /// the sets can be in use by the GPU anymore.
+    pub fn reset(&mut self, context: &VulkanContext) -> Result<()> {
+        self.ready_pools.append(&mut self.full_pools);
+
+        for pool in self.ready_pools.iter_mut() {
+            unsafe {
+                context
+                    .device
+                    .reset_descriptor_pool(pool.pool, vk::DescriptorPoolResetFlags::empty())
+                    .context("vkResetDescriptorPool", "descriptor pool")?;
+            }
+            pool.left = pool.capacity.clone();
+        }
+
+        Ok(())
+    }
+
+    /// Destroys the pools, which frees every set allocated from them.
+    pub fn destroy(&self, context: &VulkanContext) {
+        for pool in self.ready_pools.iter().chain(self.full_pools.iter()) {
+            unsafe { context.device.destroy_descriptor_pool(pool.pool, None) };
+        }
+    }
+
+    fn create_pool(
+        &mut self,
+        device: &ash::Device,
+        layout: &DescriptorSetLayout,
+    ) -> Result<DescriptorPool> {
+        let pool_sizes = pool_sizes(&self.ratios, self.sets_per_pool, layout.bindings());
+
+        let create_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(self.sets_per_pool)
+            .pool_sizes(&pool_sizes);
+
+        let pool = unsafe {
+            device
+                .create_descriptor_pool(&create_info, None)
+                .context("vkCreateDescriptorPool", "descriptor pool")?
+        };
+
+        let capacity = PoolCapacity {
+            sets: self.sets_per_pool,
+            descriptors: pool_sizes,
+        };
+        self.sets_per_pool = (self.sets_per_pool * 2).min(MAX_SETS_PER_POOL);
+
+        Ok(DescriptorPool {
+            pool,
+            left: capacity.clone(),
+            capacity,
+        })
+    }
+}
+
+fn allocate_set(
+    device: &ash::Device,
+    pool: vk::DescriptorPool,
+    layout: &DescriptorSetLayout,
+) -> Result<vk::DescriptorSet> {
+    let set_layouts = [layout.handle()];
+    let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+        .descriptor_pool(pool)
+        .set_layouts(&set_layouts);
+
+    unsafe {
+        device
+            .allocate_descriptor_sets(&allocate_info)
+            .context("vkAllocateDescriptorSets", "descriptor set")
+            .map(|sets| sets[0])
+    }
+}
+
+/// Descriptor counts for a pool of `set_count` sets, with enough of each type for at least one
+/// set with `bindings` even if the ratios say otherwise.
+fn pool_sizes(
+    ratios: &[(vk::DescriptorType, f32)],
+    set_count: u32,
+    bindings: &[DescriptorBinding],
+) -> Vec<vk::DescriptorPoolSize> {
+    let mut pool_sizes: Vec<vk::DescriptorPoolSize> = ratios
+        .iter()
+        .map(|&(ty, ratio)| vk::DescriptorPoolSize {
+            ty,
+            descriptor_count: (ratio * set_count as f32).ceil() as u32,
+        })
+        .collect();
+
+    for binding in bindings {
+        let needed: u32 = bindings
+            .iter()
+            .filter(|other| other.descriptor_type == binding.descriptor_type)
+            .map(|other| other.count)
+            .sum();
+
+        match pool_sizes
+            .iter_mut()
+            .find(|pool_size| pool_size.ty == binding.descriptor_type)
+        {
+            Some(pool_size) => pool_size.descriptor_count = pool_size.descriptor_count.max(needed),
+            None => pool_sizes.push(vk::DescriptorPoolSize {
+                ty: binding.descriptor_type,
+                descriptor_count: needed,
+            }),
+        }
+    }
+
+    pool_sizes
+}
+
+/// Collects buffers and images for the bindings of a set, then writes them all at once.
+///
+/// ```no_run
+/// # use antithesis::{context::VulkanContext, descriptor::{DescriptorWriter, UniformBuffer}};
+/// # fn write(context: &VulkanContext, camera: &UniformBuffer<[f32; 16]>, sets: &[ash::vk::DescriptorSet]) {
+/// for (frame_index, &set) in sets.iter().enumerate() {
+///     DescriptorWriter::new()
+///         .uniform_buffer(0, camera.descriptor_info(frame_index))
+///         .write(context, set);
+/// }
+/// # }
+/// ```
+#[derive(Default)]
+pub struct DescriptorWriter {
+    buffers: Vec<(u32, vk::DescriptorType, vk::DescriptorBufferInfo)>,
+    images: Vec<(u32, vk::DescriptorType, vk::DescriptorImageInfo)>,
+}
+
+impl DescriptorWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn uniform_buffer(self, binding: u32, info: vk::DescriptorBufferInfo) -> Self {
+        self.buffer(binding, vk::DescriptorType::UNIFORM_BUFFER, info)
+    }
+
+    pub fn buffer(
+        mut self,
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+        info: vk::DescriptorBufferInfo,
+    ) -> Self {
+        self.buffers.push((binding, descriptor_type, info));
+        self
+    }
+
+    /// `layout` is the one the image will be in when the shaders read it, usually
+    /// `SHADER_READ_ONLY_OPTIMAL`.
+    pub fn image(
+        mut self,
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+        image_view: vk::ImageView,
+        sampler: vk::Sampler,
+        layout: vk::ImageLayout,
+    ) -> Self {
+        let info = vk::DescriptorImageInfo {
+            sampler,
+            image_view,
+            image_layout: layout,
+        };
+        self.images.push((binding, descriptor_type, info));
+        self
+    }
+
+    /// Points `set`'s bindings at everything added so far. The set can't be in use by a frame
+    /// in flight.
+    pub fn write(&self, context: &VulkanContext, set: vk::DescriptorSet) {
+        let buffer_writes = self.buffers.iter().map(|(binding, ty, info)| {
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(set)
+                .dst_binding(*binding)
+                .descriptor_type(*ty)
+                .buffer_info(std::slice::from_ref(info))
+        });
+        let image_writes = self.images.iter().map(|(binding, ty, info)| {
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(set)
+                .dst_binding(*binding)
+                .descriptor_type(*ty)
+                .image_info(std::slice::from_ref(info))
+        });
+        let writes: Vec<_> = buffer_writes.chain(image_writes).collect();
+
+        unsafe { context.device.update_descriptor_sets(&writes, &[]) };
+    }
+}
+
+/// A `T` the shaders can read as a uniform block, with a copy per frame in flight so updating it
+/// never touches one the GPU might still be reading.
+///
+/// `T` has to match the block's std140 layout, so mind the padding of `vec3`s.
+pub struct UniformBuffer<T> {
+    buffers: Vec<(vk::Buffer, Allocation)>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Pod> UniformBuffer<T> {
+    /// Errors for a zero-sized `T`, since there'd be nothing to put in the buffers.
+    pub fn new(context: &VulkanContext, value: &T) -> Result<Self> {
+        if std::mem::size_of::<T>() == 0 {
+            return Err(AntithesisError::EmptyBuffer("uniform buffer"));
+        }
+
+        let mut uniform_buffer = UniformBuffer {
+            buffers: vec![],
+            _marker: PhantomData,
+        };
+
+        for _ in 0..context.frames_in_flight() {
+            let buffer_and_allocation = create_buffer(
+                context,
+                std::mem::size_of::<T>() as vk::DeviceSize,
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                MemoryLocation::CpuToGpu,
+            );
+            match buffer_and_allocation {
+                Ok(buffer_and_allocation) => uniform_buffer.buffers.push(buffer_and_allocation),
+                Err(err) => {
+                    uniform_buffer.destroy(context);
+                    return Err(err);
+                }
+            }
+        }
+
+        for frame_index in 0..uniform_buffer.buffers.len() {
+            uniform_buffer.update(frame_index, value);
+        }
+
+        Ok(uniform_buffer)
+    }
+
+    /// Writes `value` into the copy for `frame_index`, which is safe from `Game::render` since
+    /// that frame's previous submission has finished by then.
+    pub fn update(&mut self, frame_index: usize, value: &T) {
+        let bytes = bytes_of(value);
+
+        // the allocation can be bigger than asked for
+        self.buffers[frame_index]
+            .1
+            .mapped_slice_mut()
+            .expect("CpuToGpu memory is always mapped")[..bytes.len()]
+            .copy_from_slice(bytes);
+    }
+
+    pub fn buffer(&self, frame_index: usize) -> vk::Buffer {
+        self.buffers[frame_index].0
+    }
+
+    /// For pointing a descriptor set at the copy for `frame_index`.
+    pub fn descriptor_info(&self, frame_index: usize) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo {
+            buffer: self.buffer(frame_index),
+            offset: 0,
+            range: std::mem::size_of::<T>() as vk::DeviceSize,
+        }
+    }
+
+    /// Frees the buffers; no frame in flight can be using them anymore.
+    pub fn destroy(self, context: &VulkanContext) {
+        for (buffer, allocation) in self.buffers {
+            destroy_buffer(context, buffer, allocation);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(pool_sizes: &[vk::DescriptorPoolSize], ty: vk::DescriptorType) -> Option<u32> {
+        pool_sizes
+            .iter()
+            .find(|pool_size| pool_size.ty == ty)
+            .map(|pool_size| pool_size.descriptor_count)
+    }
+
+    #[test]
+    fn pool_sizes_scale_with_set_count() {
+        let pool_sizes = pool_sizes(&DEFAULT_POOL_RATIOS, 64, &[]);
+
+        assert_eq!(
+            count(&pool_sizes, vk::DescriptorType::UNIFORM_BUFFER),
+            Some(128)
+        );
+        assert_eq!(
+            count(&pool_sizes, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+            Some(256)
+        );
+    }
+
+    #[test]
+    fn pool_sizes_fit_at_least_one_set() {
+        let stages = vk::ShaderStageFlags::FRAGMENT;
+        let bindings = [
+            DescriptorBinding {
+                count: 300,
+                ..DescriptorBinding::combined_image_sampler(0, stages)
+            },
+            DescriptorBinding::combined_image_sampler(1, stages),
+            DescriptorBinding {
+                binding: 2,
+                descriptor_type: vk::DescriptorType::INPUT_ATTACHMENT,
+                count: 1,
+                stages,
+            },
+        ];
+
+        let pool_sizes = pool_sizes(&DEFAULT_POOL_RATIOS, 64, &bindings);
+
+        assert_eq!(
+            count(&pool_sizes, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+            Some(301)
+        );
+        assert_eq!(
+            count(&pool_sizes, vk::DescriptorType::INPUT_ATTACHMENT),
+            Some(1)
+        );
+        assert_eq!(
+            count(&pool_sizes, vk::DescriptorType::UNIFORM_BUFFER),
+            Some(128)
+        );
+    }
+
+    #[test]
+    fn pools_run_out_of_sets_and_descriptors() {
+        let stages = vk::ShaderStageFlags::FRAGMENT;
+        let bindings = [
+            DescriptorBinding::uniform_buffer(0, stages),
+            DescriptorBinding::combined_image_sampler(1, stages),
+        ];
+        let mut capacity = PoolCapacity {
+            sets: 3,
+            descriptors: pool_sizes(&DEFAULT_POOL_RATIOS, 1, &bindings),
+        };
+
+        // sized for one set, the ratios leave room for two uniform buffers and one storage image
+        assert!(capacity.take(&bindings));
+        assert!(capacity.take(&bindings));
+        assert!(!capacity.take(&bindings));
+        assert_eq!(capacity.sets, 1);
+
+        let storage_image = [DescriptorBinding {
+            binding: 0,
+            descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+            count: 1,
+            stages,
+        }];
+        assert!(capacity.take(&storage_image));
+        assert_eq!(capacity.sets, 0);
+        assert!(!capacity.take(&[]));
+    }
+}
@@ -1,15 +1,22 @@
-use std::{
-    collections::HashSet,
-    ffi::{c_char, CStr},
-};
+use std::ffi::{c_char, CStr, CString};
 
 use ash::{extensions::khr::Swapchain, vk, Instance};
 
-use crate::{app::SurfaceInfo, swapchain::SwapChainSupportDetail};
+use crate::{
+    app::SurfaceInfo,
+    config::AppConfig,
+    error::{AntithesisError, Result, VkResultExt},
+    features::{DeviceFeature, EnabledDeviceFeatures, FeatureFlags},
+    swapchain::SwapChainSupportDetail,
+};
 
 pub struct QueueFamilyIndices {
     pub graphics_family: Option<u32>,
     pub present_family: Option<u32>,
+    /// A family that can transfer but not draw, usually a separate DMA engine for uploads.
+    pub transfer_family: Option<u32>,
+    /// A family that can compute but not draw, for compute work alongside rendering.
+    pub compute_family: Option<u32>,
 }
 
 impl QueueFamilyIndices {
@@ -17,65 +24,101 @@ impl QueueFamilyIndices {
         QueueFamilyIndices {
             graphics_family: None,
             present_family: None,
+            transfer_family: None,
+            compute_family: None,
         }
     }
 
-    pub fn is_complete(&self) -> bool {
-        self.graphics_family.is_some() && self.present_family.is_some()
+    /// Headless contexts don't present, so they only need a graphics family.
+    pub fn is_complete(&self, needs_present: bool) -> bool {
+        self.graphics_family.is_some() && (!needs_present || self.present_family.is_some())
+    }
+
+    /// Every distinct family in use, which is what queues get created for.
+    pub fn unique_families(&self) -> Vec<u32> {
+        let mut families = vec![];
+        for family in [
+            self.graphics_family,
+            self.present_family,
+            self.transfer_family,
+            self.compute_family,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if !families.contains(&family) {
+                families.push(family);
+            }
+        }
+        families
     }
 }
 
-fn find_queue_family(
+pub fn find_queue_family(
     instance: &ash::Instance,
     physical_device: vk::PhysicalDevice,
-    surface_info: &SurfaceInfo,
-) -> QueueFamilyIndices {
+    surface_info: Option<&SurfaceInfo>,
+) -> Result<QueueFamilyIndices> {
     let queue_families =
         unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
 
     let mut queue_family_indices = QueueFamilyIndices::new();
+    // transfer-only beats transfer alongside compute, which still beats sharing with graphics
+    let mut is_transfer_dedicated = false;
 
-    let mut index = 0;
-    for queue_family in queue_families.iter() {
-        if queue_family.queue_count > 0
-            && queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS)
-        {
-            queue_family_indices.graphics_family = Some(index);
+    for (index, queue_family) in queue_families.iter().enumerate() {
+        let index = index as u32;
+        if queue_family.queue_count == 0 {
+            continue;
         }
 
-        let is_present_support = unsafe {
-            surface_info
-                .surface_loader
-                .get_physical_device_surface_support(
-                    physical_device,
-                    index as u32,
-                    surface_info.surface,
-                )
-                .unwrap()
-        };
+        let flags = queue_family.queue_flags;
+        let is_graphics = flags.contains(vk::QueueFlags::GRAPHICS);
+        let is_compute = flags.contains(vk::QueueFlags::COMPUTE);
+        // graphics and compute queues can always transfer, even without the flag
+        let is_transfer = flags.contains(vk::QueueFlags::TRANSFER) || is_graphics || is_compute;
 
-        if queue_family.queue_count > 0 && is_present_support {
-            queue_family_indices.present_family = Some(index);
+        if is_graphics && queue_family_indices.graphics_family.is_none() {
+            queue_family_indices.graphics_family = Some(index);
         }
 
-        if queue_family_indices.is_complete() {
-            break;
+        if is_compute && !is_graphics && queue_family_indices.compute_family.is_none() {
+            queue_family_indices.compute_family = Some(index);
         }
 
-        index += 1;
-    }
+        if is_transfer && !is_graphics && !is_transfer_dedicated {
+            queue_family_indices.transfer_family = Some(index);
+            is_transfer_dedicated = !is_compute;
+        }
 
-    queue_family_indices
-}
+        if let Some(surface_info) = surface_info {
+            let is_present_support = unsafe {
+                surface_info
+                    .surface_loader
+                    .get_physical_device_surface_support(
+                        physical_device,
+                        index,
+                        surface_info.surface,
+                    )
+                    .context("vkGetPhysicalDeviceSurfaceSupportKHR", "queue family")?
+            };
+
+            // presenting from the graphics family avoids sharing swapchain images across families
+            let is_graphics_family = queue_family_indices.graphics_family == Some(index);
+            if is_present_support
+                && (queue_family_indices.present_family.is_none() || is_graphics_family)
+            {
+                queue_family_indices.present_family = Some(index);
+            }
+        }
+    }
 
-pub struct DeviceExtension {
-    pub names: [&'static str; 1],
-    //    pub raw_names: [*const i8; 1],
+    Ok(queue_family_indices)
 }
 
-const DEVICE_EXTENSIONS: DeviceExtension = DeviceExtension {
-    names: ["VK_KHR_swapchain"],
-};
+/// Environment variable that forces a GPU by index, UUID or (part of its) name, taking
+/// precedence over `AppConfig::device`.
+pub const DEVICE_SELECTOR_VAR: &str = "ANTITHESIS_DEVICE";
 
 /// Helper function to convert [c_char; SIZE] to string
 pub fn vk_to_string(raw_string_array: &[c_char]) -> String {
@@ -85,97 +128,265 @@ pub fn vk_to_string(raw_string_array: &[c_char]) -> String {
         CStr::from_ptr(pointer)
     };
 
-    raw_string
-        .to_str()
-        .expect("Failed to convert vulkan raw string.")
-        .to_owned()
+    raw_string.to_string_lossy().into_owned()
 }
 
-fn check_device_extension_support(
+fn available_device_extensions(
     instance: &ash::Instance,
     physical_device: vk::PhysicalDevice,
-) -> bool {
+) -> Result<Vec<String>> {
     let available_extensions = unsafe {
         instance
             .enumerate_device_extension_properties(physical_device)
-            .expect("Failed to get device extension properties.")
+            .context("vkEnumerateDeviceExtensionProperties", "physical device")?
     };
 
-    let mut available_extension_names = vec![];
+    let available_extension_names: Vec<String> = available_extensions
+        .iter()
+        .map(|extension| vk_to_string(&extension.extension_name))
+        .collect();
+    tracing::trace!(extensions = ?available_extension_names, "available device extensions");
 
-    println!("\tAvailable Device Extensions: ");
-    for extension in available_extensions.iter() {
-        let extension_name: String = vk_to_string(&extension.extension_name);
-        println!(
-            "\t\tName: {}, Version: {}",
-            extension_name, extension.spec_version
-        );
+    Ok(available_extension_names)
+}
+
+/// Everything `config` requires, plus the swapchain when there's a window to present to.
+fn required_device_extensions(config: &AppConfig, needs_present: bool) -> Vec<String> {
+    let mut required_extensions = config.required_extensions.clone();
+    if needs_present {
+        required_extensions.push(Swapchain::name().to_string_lossy().into_owned());
+    }
+    required_extensions
+}
+
+/// Why `physical_device` can't be used, or `None` if it can.
+fn find_rejection_reason(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    properties: &vk::PhysicalDeviceProperties,
+    surface_info: Option<&SurfaceInfo>,
+    config: &AppConfig,
+) -> Result<Option<String>> {
+    let indices = find_queue_family(instance, physical_device, surface_info)?;
+    if indices.graphics_family.is_none() {
+        return Ok(Some("no graphics queue family".to_owned()));
+    }
+    if !indices.is_complete(surface_info.is_some()) {
+        return Ok(Some("no queue family can present to the window".to_owned()));
+    }
+
+    let available_extensions = available_device_extensions(instance, physical_device)?;
+    let missing_extensions: Vec<String> =
+        required_device_extensions(config, surface_info.is_some())
+            .into_iter()
+            .filter(|extension| !available_extensions.contains(extension))
+            .collect();
+    if !missing_extensions.is_empty() {
+        return Ok(Some(format!(
+            "missing device extensions {:?}",
+            missing_extensions
+        )));
+    }
 
-        available_extension_names.push(extension_name);
+    let supported_features = FeatureFlags::query(instance, physical_device, properties, config);
+    let missing_features: Vec<DeviceFeature> = config
+        .required_features
+        .iter()
+        .copied()
+        .filter(|&feature| !supported_features.is_supported(feature))
+        .collect();
+    if !missing_features.is_empty() {
+        return Ok(Some(format!(
+            "missing device features {:?}",
+            missing_features
+        )));
     }
 
-    let mut required_extensions = HashSet::new();
-    for extension in DEVICE_EXTENSIONS.names.iter() {
-        required_extensions.insert(extension.to_string());
+    // offscreen rendering doesn't need a swapchain at all
+    if let Some(surface_info) = surface_info {
+        let swapchain_support = SwapChainSupportDetail::query(&physical_device, surface_info)?;
+        if swapchain_support.formats.is_empty() || swapchain_support.present_modes.is_empty() {
+            return Ok(Some(
+                "no surface formats or present modes for the window".to_owned(),
+            ));
+        }
     }
 
-    for extension_name in available_extension_names.iter() {
-        required_extensions.remove(extension_name);
+    let max_image_dimension = properties.limits.max_image_dimension2_d;
+    if config.width.max(config.height) > max_image_dimension {
+        return Ok(Some(format!(
+            "{}x{} is larger than the {} maximum image size",
+            config.width, config.height, max_image_dimension
+        )));
     }
 
-    return required_extensions.is_empty();
+    Ok(None)
+}
+
+/// Higher is better: device type first, then how much device-local memory it has.
+fn score_physical_device(
+    properties: &vk::PhysicalDeviceProperties,
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+) -> u64 {
+    let type_score = match properties.device_type {
+        vk::PhysicalDeviceType::DISCRETE_GPU => 4,
+        vk::PhysicalDeviceType::INTEGRATED_GPU => 3,
+        vk::PhysicalDeviceType::VIRTUAL_GPU => 2,
+        vk::PhysicalDeviceType::CPU => 1,
+        _ => 0,
+    };
+
+    // in MiB, so it can't spill over into the type score
+    let vram = device_local_memory(memory_properties) >> 20;
+
+    (type_score << 32) + vram.min(u32::MAX as u64)
+}
+
+fn device_local_memory(memory_properties: &vk::PhysicalDeviceMemoryProperties) -> u64 {
+    memory_properties.memory_heaps[..memory_properties.memory_heap_count as usize]
+        .iter()
+        .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+        .map(|heap| heap.size)
+        .sum()
 }
 
-fn is_physical_device_suitable(
+/// The device's UUID, which stays the same across runs unlike its index. Only available with
+/// Vulkan 1.1.
+pub fn device_uuid(
     instance: &ash::Instance,
     physical_device: vk::PhysicalDevice,
-    surface_info: &SurfaceInfo,
-) -> bool {
-    let _device_features = unsafe { instance.get_physical_device_features(physical_device) };
-
-    let indices = find_queue_family(instance, physical_device, surface_info);
-
-    let is_queue_family_supported = indices.is_complete();
-    let is_device_extension_supported = check_device_extension_support(instance, physical_device);
-    let is_swapchain_supported = if is_device_extension_supported {
-        let swapchain_support = SwapChainSupportDetail::query(&physical_device, surface_info);
-        !swapchain_support.formats.is_empty() && !swapchain_support.present_modes.is_empty()
-    } else {
-        false
-    };
+    properties: &vk::PhysicalDeviceProperties,
+    config: &AppConfig,
+) -> Option<String> {
+    if config.api_version < vk::API_VERSION_1_1 || properties.api_version < vk::API_VERSION_1_1 {
+        return None;
+    }
 
-    return is_queue_family_supported && is_device_extension_supported && is_swapchain_supported;
+    let mut id_properties = vk::PhysicalDeviceIDProperties::default();
+    let mut properties2 = vk::PhysicalDeviceProperties2::builder().push_next(&mut id_properties);
+    unsafe { instance.get_physical_device_properties2(physical_device, &mut properties2) };
+
+    let uuid = id_properties.device_uuid;
+    let hex = |bytes: &[u8]| -> String { bytes.iter().map(|b| format!("{:02x}", b)).collect() };
+    Some(format!(
+        "{}-{}-{}-{}-{}",
+        hex(&uuid[..4]),
+        hex(&uuid[4..6]),
+        hex(&uuid[6..8]),
+        hex(&uuid[8..10]),
+        hex(&uuid[10..])
+    ))
 }
 
-// todo: split to physical & logical device construction
-// todo: isolate unsafe blocks instead of making this fn unsafe
-pub fn pick_physical_device(instance: &Instance, surface_info: &SurfaceInfo) -> vk::PhysicalDevice {
+/// Whether `selector` (an index, UUID or part of the name) picks out this device.
+fn matches_device_selector(selector: &str, index: usize, name: &str, uuid: Option<&str>) -> bool {
+    if let Ok(selected_index) = selector.parse::<usize>() {
+        return selected_index == index;
+    }
+
+    let without_dashes = |uuid: &str| uuid.replace('-', "").to_lowercase();
+    if uuid.is_some_and(|uuid| without_dashes(uuid) == without_dashes(selector)) {
+        return true;
+    }
+
+    name.to_lowercase().contains(&selector.to_lowercase())
+}
+
+/// Picks the highest scoring suitable device, or the one forced through `ANTITHESIS_DEVICE` (or
+/// `AppConfig::device`), logging every candidate and why it was or wasn't chosen.
+pub fn pick_physical_device(
+    instance: &Instance,
+    surface_info: Option<&SurfaceInfo>,
+    config: &AppConfig,
+) -> Result<vk::PhysicalDevice> {
     let physical_devices = unsafe {
         instance
             .enumerate_physical_devices()
-            .expect("Failed to enumerate physical devices!")
+            .context("vkEnumeratePhysicalDevices", "instance")?
     };
 
-    let result = physical_devices.iter().find(|physical_device| {
-        is_physical_device_suitable(instance, **physical_device, surface_info)
-    });
+    let selector = std::env::var(DEVICE_SELECTOR_VAR)
+        .ok()
+        .or_else(|| config.device_selector.clone())
+        .filter(|selector| !selector.is_empty());
+
+    let mut best_device = None;
+    let mut is_selector_matched = false;
+
+    for (index, &physical_device) in physical_devices.iter().enumerate() {
+        let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+        let memory_properties =
+            unsafe { instance.get_physical_device_memory_properties(physical_device) };
+        let name = vk_to_string(&properties.device_name);
+        let uuid = device_uuid(instance, physical_device, &properties, config);
+
+        let rejection_reason = match &selector {
+            Some(selector) if !matches_device_selector(selector, index, &name, uuid.as_deref()) => {
+                Some(format!("not selected by {}", selector))
+            }
+            _ => {
+                is_selector_matched |= selector.is_some();
+                find_rejection_reason(instance, physical_device, &properties, surface_info, config)?
+            }
+        };
+
+        let score = score_physical_device(&properties, &memory_properties);
+        let vram_mib = device_local_memory(&memory_properties) >> 20;
+
+        match rejection_reason {
+            Some(reason) => tracing::info!(
+                index,
+                %name,
+                device_type = ?properties.device_type,
+                vram_mib,
+                uuid = uuid.as_deref().unwrap_or("unknown"),
+                %reason,
+                "rejected GPU"
+            ),
+            None => {
+                tracing::info!(
+                    index,
+                    %name,
+                    device_type = ?properties.device_type,
+                    vram_mib,
+                    uuid = uuid.as_deref().unwrap_or("unknown"),
+                    score,
+                    "candidate GPU"
+                );
+
+                if best_device
+                    .as_ref()
+                    .is_none_or(|(_, best_score, _)| score > *best_score)
+                {
+                    best_device = Some((physical_device, score, name));
+                }
+            }
+        }
+    }
 
-    *result.expect("Failed to find a suitable GPU!")
+    if let Some(selector) = selector.filter(|_| !is_selector_matched) {
+        return Err(AntithesisError::DeviceNotFound(selector));
+    }
+
+    let (physical_device, _, name) = best_device.ok_or(AntithesisError::NoSuitableGpu)?;
+    tracing::info!(%name, "using GPU");
+
+    Ok(physical_device)
 }
 
+/// Creates the device with `config`'s required extensions and features, plus whichever
+/// optional ones `physical_device` supports.
 pub fn create_logical_device(
     instance: &ash::Instance,
     physical_device: &vk::PhysicalDevice,
-    surface_info: &SurfaceInfo,
-) -> (ash::Device, QueueFamilyIndices) {
-    let indices = find_queue_family(instance, *physical_device, surface_info);
-
-    let mut unique_queue_families = HashSet::new();
-    unique_queue_families.insert(indices.graphics_family.unwrap());
-    unique_queue_families.insert(indices.present_family.unwrap());
-
-    // Single queue with priority 1, supporting graphics as found above
-    let queue_create_infos = unique_queue_families
+    surface_info: Option<&SurfaceInfo>,
+    config: &AppConfig,
+) -> Result<(ash::Device, QueueFamilyIndices, EnabledDeviceFeatures)> {
+    let indices = find_queue_family(instance, *physical_device, surface_info)?;
+
+    // Single queue with priority 1 per family, graphics and whichever of the others were found
+    let queue_create_infos = indices
+        .unique_families()
         .iter()
         .map(|queue_family| {
             *vk::DeviceQueueCreateInfo::builder()
@@ -184,20 +395,77 @@ pub fn create_logical_device(
         })
         .collect::<Vec<_>>();
 
-    // enable swapchain extension here (possibly unchecked?)
-    let device_extension_names_raw = [Swapchain::name().as_ptr()];
+    // selection already made sure the required ones are there
+    let available_extensions = available_device_extensions(instance, *physical_device)?;
+    let mut extensions = required_device_extensions(config, surface_info.is_some());
+    for extension in config.optional_extensions.iter() {
+        if extensions.contains(extension) {
+            continue;
+        }
+        if available_extensions.contains(extension) {
+            extensions.push(extension.clone());
+        } else {
+            tracing::info!(%extension, "optional device extension isn't supported, skipping it");
+        }
+    }
+    // a name with a nul in it can't be available, so selection would have rejected it
+    let extension_names: Vec<CString> = extensions
+        .iter()
+        .filter_map(|extension| CString::new(extension.as_str()).ok())
+        .collect();
+    let extension_names_raw: Vec<*const c_char> =
+        extension_names.iter().map(|name| name.as_ptr()).collect();
+
+    let properties = unsafe { instance.get_physical_device_properties(*physical_device) };
+    let supported_features = FeatureFlags::query(instance, *physical_device, &properties, config);
+    let mut enabled_flags = FeatureFlags {
+        vulkan12: supported_features
+            .vulkan12
+            .map(|_| vk::PhysicalDeviceVulkan12Features::default()),
+        ..Default::default()
+    };
+    let mut features = config.required_features.clone();
+    for &feature in config.optional_features.iter() {
+        if features.contains(&feature) {
+            continue;
+        }
+        if supported_features.is_supported(feature) {
+            features.push(feature);
+        } else {
+            tracing::info!(
+                ?feature,
+                "optional device feature isn't supported, skipping it"
+            );
+        }
+    }
+    for &feature in features.iter() {
+        enabled_flags.enable(feature);
+    }
 
-    // Info for creating the device with enabled extensions and queue info
-    let device_create_info = vk::DeviceCreateInfo::builder()
+    // Info for creating the device with enabled extensions, features and queue info
+    let mut device_create_info = vk::DeviceCreateInfo::builder()
         .queue_create_infos(&queue_create_infos)
-        .enabled_extension_names(&device_extension_names_raw);
+        .enabled_extension_names(&extension_names_raw)
+        .enabled_features(&enabled_flags.core);
+    if let Some(vulkan12) = enabled_flags.vulkan12.as_mut() {
+        device_create_info = device_create_info.push_next(vulkan12);
+    }
 
     // Create the physical device!
     let device: ash::Device = unsafe {
         instance
             .create_device(*physical_device, &device_create_info, None)
-            .expect("Failed to create logical device!")
+            .context("vkCreateDevice", "logical device")?
     };
 
-    (device, indices)
+    tracing::info!(?extensions, ?features, "created device");
+
+    Ok((
+        device,
+        indices,
+        EnabledDeviceFeatures {
+            extensions,
+            features,
+        },
+    ))
 }
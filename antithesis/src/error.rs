@@ -0,0 +1,97 @@
+use std::path::PathBuf;
+
+use ash::{prelude::VkResult, vk};
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, AntithesisError>;
+
+/// Everything that can go wrong while setting up or running the engine.
+#[derive(Debug, Error)]
+pub enum AntithesisError {
+    #[error("failed to load the Vulkan library: {0}")]
+    Loading(#[from] ash::LoadingError),
+
+    /// A Vulkan call failed; `call` is the entry point and `resource` what it was working on.
+    #[error("{call} failed ({resource}): {result}")]
+    Vulkan {
+        call: &'static str,
+        resource: &'static str,
+        result: vk::Result,
+    },
+
+    /// Only returned with `AppConfig::strict_validation` on.
+    #[error("the validation layer reported {0} error(s)")]
+    Validation(usize),
+
+    #[error("failed to find a suitable GPU")]
+    NoSuitableGpu,
+
+    #[error("no GPU matches \"{0}\"")]
+    DeviceNotFound(String),
+
+    #[error("failed to find a suitable memory type (type bits {type_bits:#b}, {properties:?})")]
+    NoSuitableMemoryType {
+        type_bits: u32,
+        properties: vk::MemoryPropertyFlags,
+    },
+
+    #[error("push constants are {size} bytes, the device allows at most {max}")]
+    PushConstantsTooLarge { size: u32, max: u32 },
+
+    /// Vulkan buffers can't be empty, so there has to be some data to put in them.
+    #[error("can't create an empty {0}")]
+    EmptyBuffer(&'static str),
+
+    #[error("can't render to a {width}x{height} image")]
+    EmptyImage { width: u32, height: u32 },
+
+    /// The offscreen image only has contents once a frame was rendered into it.
+    #[error("no frame has been rendered yet")]
+    NoFrameRendered,
+
+    #[error("unsupported image format {0:?}")]
+    UnsupportedFormat(vk::Format),
+
+    #[error("invalid SPIR-V: {0}")]
+    InvalidShader(&'static str),
+
+    #[error("SPIR-V {}.{} is too new, the device takes up to {}.{}", version.0, version.1, max.0, max.1)]
+    UnsupportedSpirvVersion {
+        version: (u32, u32),
+        max: (u32, u32),
+    },
+
+    #[error("failed to read shader {path:?}: {source}")]
+    ShaderFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[cfg(feature = "hot-reload")]
+    #[error("failed to watch shaders: {0}")]
+    Watch(#[from] antithesis_watch::notify::Error),
+
+    #[error("failed to create the window: {0}")]
+    Window(#[from] winit::error::OsError),
+
+    #[error("failed to encode PNG: {0}")]
+    Png(#[from] png::EncodingError),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub(crate) trait VkResultExt<T> {
+    /// Tags a failed Vulkan call with what was being called, on what.
+    fn context(self, call: &'static str, resource: &'static str) -> Result<T>;
+}
+
+impl<T> VkResultExt<T> for VkResult<T> {
+    fn context(self, call: &'static str, resource: &'static str) -> Result<T> {
+        self.map_err(|result| AntithesisError::Vulkan {
+            call,
+            resource,
+            result,
+        })
+    }
+}
@@ -0,0 +1,96 @@
+use ash::vk;
+
+use crate::config::AppConfig;
+
+/// Optional device capabilities a game can ask for through `AppConfig::require_feature` or
+/// `AppConfig::optional_feature`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceFeature {
+    SamplerAnisotropy,
+    /// Wireframe and point polygon modes.
+    FillModeNonSolid,
+    WideLines,
+    MultiDrawIndirect,
+    /// Bindless-style descriptor arrays; needs Vulkan 1.2 (`AppConfig::api_version(1, 2)`).
+    DescriptorIndexing,
+    /// Needs Vulkan 1.2 (`AppConfig::api_version(1, 2)`).
+    TimelineSemaphore,
+}
+
+/// What a physical device reports it can do, or everything that's going to be enabled on a
+/// logical device.
+#[derive(Clone, Default)]
+pub(crate) struct FeatureFlags {
+    pub core: vk::PhysicalDeviceFeatures,
+    // only filled in when both the instance and the device are 1.2
+    pub vulkan12: Option<vk::PhysicalDeviceVulkan12Features>,
+}
+
+impl FeatureFlags {
+    pub fn query(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        properties: &vk::PhysicalDeviceProperties,
+        config: &AppConfig,
+    ) -> Self {
+        let core = unsafe { instance.get_physical_device_features(physical_device) };
+
+        let vulkan12 = (config.api_version >= vk::API_VERSION_1_2
+            && properties.api_version >= vk::API_VERSION_1_2)
+            .then(|| {
+                let mut vulkan12 = vk::PhysicalDeviceVulkan12Features::default();
+                let mut features2 = vk::PhysicalDeviceFeatures2::builder().push_next(&mut vulkan12);
+                unsafe { instance.get_physical_device_features2(physical_device, &mut features2) };
+                vulkan12
+            });
+
+        FeatureFlags { core, vulkan12 }
+    }
+
+    pub fn is_supported(&self, feature: DeviceFeature) -> bool {
+        self.clone()
+            .flags(feature)
+            .is_some_and(|flags| flags.into_iter().all(|flag| *flag == vk::TRUE))
+    }
+
+    /// Turns `feature` on, unless it's a 1.2 feature and 1.2 isn't in use.
+    pub fn enable(&mut self, feature: DeviceFeature) {
+        for flag in self.flags(feature).into_iter().flatten() {
+            *flag = vk::TRUE;
+        }
+    }
+
+    /// The flags that make up `feature`, `None` if they need structs this device doesn't have.
+    fn flags(&mut self, feature: DeviceFeature) -> Option<Vec<&mut vk::Bool32>> {
+        let core = &mut self.core;
+        let flags = match feature {
+            DeviceFeature::SamplerAnisotropy => vec![&mut core.sampler_anisotropy],
+            DeviceFeature::FillModeNonSolid => vec![&mut core.fill_mode_non_solid],
+            DeviceFeature::WideLines => vec![&mut core.wide_lines],
+            DeviceFeature::MultiDrawIndirect => vec![&mut core.multi_draw_indirect],
+            DeviceFeature::DescriptorIndexing => {
+                let vulkan12 = self.vulkan12.as_mut()?;
+                vec![
+                    &mut vulkan12.descriptor_indexing,
+                    &mut vulkan12.runtime_descriptor_array,
+                    &mut vulkan12.descriptor_binding_partially_bound,
+                    &mut vulkan12.descriptor_binding_variable_descriptor_count,
+                    &mut vulkan12.shader_sampled_image_array_non_uniform_indexing,
+                ]
+            }
+            DeviceFeature::TimelineSemaphore => {
+                vec![&mut self.vulkan12.as_mut()?.timeline_semaphore]
+            }
+        };
+
+        Some(flags)
+    }
+}
+
+/// The device extensions and features that ended up enabled, required ones plus whichever
+/// optional ones the device supported.
+#[derive(Debug, Clone, Default)]
+pub struct EnabledDeviceFeatures {
+    pub extensions: Vec<String>,
+    pub features: Vec<DeviceFeature>,
+}
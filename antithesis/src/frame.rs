@@ -0,0 +1,126 @@
+use ash::vk;
+
+use crate::{
+    mesh::Mesh,
+    pipeline::{Pipeline, PipelineLayout},
+    pod::{bytes_of, Pod},
+};
+
+/// What a game gets to see and do while one frame is being recorded.
+///
+/// The frame's render pass is already running with the default pipeline and vertex buffer
+/// bound, so draws recorded here end up in this frame only.
+pub struct FrameContext<'a> {
+    pub(crate) device: &'a ash::Device,
+    pub(crate) command_buffer: vk::CommandBuffer,
+    pub(crate) extent: vk::Extent2D,
+    pub(crate) frame_index: usize,
+    pub(crate) image_index: u32,
+    pub(crate) is_exit_requested: bool,
+}
+
+impl FrameContext<'_> {
+    /// Size of the image being rendered to, in pixels.
+    pub fn extent(&self) -> (u32, u32) {
+        (self.extent.width, self.extent.height)
+    }
+
+    /// Which of the frames in flight this is, for indexing per-frame resources.
+    pub fn frame_index(&self) -> usize {
+        self.frame_index
+    }
+
+    /// Which swapchain image this frame ends up in.
+    pub fn image_index(&self) -> u32 {
+        self.image_index
+    }
+
+    /// Draws `vertex_count` vertices from the bound vertex buffer, `instance_count` times.
+    pub fn draw(&mut self, vertex_count: u32, instance_count: u32) {
+        unsafe {
+            self.device
+                .cmd_draw(self.command_buffer, vertex_count, instance_count, 0, 0)
+        };
+    }
+
+    /// Draws `mesh` with the bound pipeline, through its indices if it has any.
+    pub fn draw_mesh(&mut self, mesh: &Mesh) {
+        self.draw_mesh_instanced(mesh, 1);
+    }
+
+    /// Draws `mesh` `instance_count` times. Its vertex buffer stays bound afterwards, in place
+    /// of the default one.
+    pub fn draw_mesh_instanced(&mut self, mesh: &Mesh, instance_count: u32) {
+        mesh.record_draw(self.device, self.command_buffer, instance_count);
+    }
+
+    /// Draws after this use `pipeline` instead of the default one.
+    pub fn bind_pipeline(&mut self, pipeline: &Pipeline) {
+        unsafe {
+            self.device.cmd_bind_pipeline(
+                self.command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline.handle(),
+            )
+        };
+    }
+
+    /// Binds `sets` starting at set number `first_set`, for pipelines made with
+    /// `pipeline_layout`.
+    pub fn bind_descriptor_sets(
+        &mut self,
+        pipeline_layout: &PipelineLayout,
+        first_set: u32,
+        sets: &[vk::DescriptorSet],
+    ) {
+        unsafe {
+            self.device.cmd_bind_descriptor_sets(
+                self.command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline_layout.handle(),
+                first_set,
+                sets,
+                &[],
+            )
+        };
+    }
+
+    /// Sets the push constants at `offset` for `stages` to `value`, for the draws after it.
+    /// `layout` has to declare a range for exactly `stages` that covers them.
+    pub fn push_constants<T: Pod>(
+        &mut self,
+        layout: &PipelineLayout,
+        stages: vk::ShaderStageFlags,
+        offset: u32,
+        value: &T,
+    ) {
+        let bytes = bytes_of(value);
+        debug_assert!(
+            layout.is_push_declared(stages, offset, bytes.len() as u32),
+            "pushing {} bytes at {} for {:?}, which the layout doesn't declare",
+            bytes.len(),
+            offset,
+            stages
+        );
+
+        unsafe {
+            self.device.cmd_push_constants(
+                self.command_buffer,
+                layout.handle(),
+                stages,
+                offset,
+                bytes,
+            )
+        };
+    }
+
+    /// The command buffer being recorded, for anything the frame API doesn't cover yet.
+    pub fn command_buffer(&self) -> vk::CommandBuffer {
+        self.command_buffer
+    }
+
+    /// Stops the frame loop after this frame has been presented.
+    pub fn request_exit(&mut self) {
+        self.is_exit_requested = true;
+    }
+}
@@ -0,0 +1,44 @@
+use ash::vk;
+use winit::event::WindowEvent;
+
+#[cfg(feature = "hot-reload")]
+use crate::hot_reload::ReloadedShader;
+use crate::{context::VulkanContext, frame::FrameContext};
+
+/// Hooks for a game running on the engine. `run_app` calls them from its frame loop; every
+/// method has an empty default so games only implement what they need.
+pub trait Game {
+    /// Called once the engine is set up, before the first frame.
+    fn init(&mut self, _context: &VulkanContext) {}
+
+    /// Called after `init`, and again with the GPU idle whenever the render pass gets recreated
+    /// (because the swapchain format changed), since pipelines are built for a render pass.
+    /// Pipelines from the previous call have to be destroyed and rebuilt.
+    fn create_pipelines(&mut self, _context: &VulkanContext, _render_pass: vk::RenderPass) {}
+
+    /// Called between frames with the shaders that changed and compiled, once no frame in
+    /// flight is using the current pipelines. Pipelines using any of them should be rebuilt,
+    /// keeping the old one if that fails, and the code that got replaced handed to
+    /// `VulkanContext::evict_shader_module` so its module doesn't pile up.
+    #[cfg(feature = "hot-reload")]
+    fn shaders_reloaded(
+        &mut self,
+        _context: &VulkanContext,
+        _render_pass: vk::RenderPass,
+        _shaders: &[ReloadedShader],
+    ) {
+    }
+
+    /// Called at the start of every frame with the seconds elapsed since the previous one.
+    fn update(&mut self, _dt: f32) {}
+
+    /// Called every frame once the swapchain image for it has been acquired.
+    fn render(&mut self, _frame: &mut FrameContext) {}
+
+    /// Called for every window event, before the engine reacts to it.
+    fn event(&mut self, _event: &WindowEvent) {}
+
+    /// Called once the frame loop has stopped and the GPU is idle, to destroy whatever `init`
+    /// created, like meshes.
+    fn exit(&mut self, _context: &VulkanContext) {}
+}
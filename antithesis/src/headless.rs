@@ -0,0 +1,340 @@
+use std::mem::ManuallyDrop;
+
+use ash::vk;
+
+use crate::{
+    config::AppConfig,
+    context::VulkanContext,
+    error::{AntithesisError, Result, VkResultExt},
+    frame::FrameContext,
+    mesh::Mesh,
+    offscreen::{
+        create_depth_target, create_offscreen_target, destroy_offscreen_target, OffscreenTarget,
+        OFFSCREEN_FORMAT,
+    },
+    pipeline::{
+        create_framebuffers, create_gfx_pipeline, create_render_pass, create_triangle_mesh,
+        triangle_shaders, Pipeline, PipelineLayout,
+    },
+    readback::{read_image, FrameCapture},
+    sync::{
+        begin_frame_commands, create_command_pool, create_frame_commands, destroy_frame_commands,
+        end_frame_commands, FrameCommands,
+    },
+};
+
+/// Renders into an offscreen image instead of a window, for machines without a display
+/// (or a GPU, with a software rasterizer like lavapipe).
+pub struct Renderer {
+    context: VulkanContext,
+
+    // only taken out to be destroyed in `drop`
+    target: ManuallyDrop<OffscreenTarget>,
+    depth_target: Option<OffscreenTarget>,
+
+    render_pass: vk::RenderPass,
+    pipeline_layout: PipelineLayout,
+    gfx_pipeline: Pipeline,
+    framebuffers: Vec<vk::Framebuffer>,
+
+    // bound by default, so games can draw it without setting anything up
+    triangle: ManuallyDrop<Mesh>,
+
+    command_pool: vk::CommandPool,
+    frame_commands: Vec<FrameCommands>,
+
+    render_fence: vk::Fence,
+    // until then the image is still undefined, with nothing to read back
+    has_rendered_frame: bool,
+}
+
+impl Renderer {
+    pub fn new(width: u32, height: u32) -> Result<Self> {
+        Self::with_config(&AppConfig::new().size(width, height))
+    }
+
+    /// Uses everything in `config` except the window options; its size becomes the image size.
+    pub fn with_config(config: &AppConfig) -> Result<Self> {
+        if config.width == 0 || config.height == 0 {
+            return Err(AntithesisError::EmptyImage {
+                width: config.width,
+                height: config.height,
+            });
+        }
+        let extent = vk::Extent2D {
+            width: config.width,
+            height: config.height,
+        };
+
+        // every frame is waited on, so per-frame resources only need one copy
+        let context = VulkanContext::new(None, &config.clone().frames_in_flight(1))?;
+
+        let mut parts = RendererParts::default();
+        if let Err(err) = parts.create(&context, extent) {
+            // dropping the context destroys the device, which can't have anything left on it
+            parts.destroy(&context);
+            return Err(err);
+        }
+        let (pipeline_layout, gfx_pipeline) = parts.pipeline.unwrap();
+
+        Ok(Renderer {
+            target: ManuallyDrop::new(parts.target.unwrap()),
+            depth_target: parts.depth_target,
+            render_pass: parts.render_pass.unwrap(),
+            pipeline_layout,
+            gfx_pipeline,
+            framebuffers: parts.framebuffers,
+            triangle: ManuallyDrop::new(parts.triangle.unwrap()),
+            command_pool: parts.command_pool.unwrap(),
+            frame_commands: parts.frame_commands,
+            render_fence: parts.render_fence.unwrap(),
+            has_rendered_frame: false,
+            context,
+        })
+    }
+
+    /// Records `render` into a frame, renders it into the offscreen image and waits for the GPU
+    /// to finish it.
+    pub fn render_frame(&mut self, render: impl FnOnce(&mut FrameContext)) -> Result<()> {
+        let device = &self.context.device;
+        let frame_commands = &self.frame_commands[0];
+
+        begin_frame_commands(
+            device,
+            frame_commands,
+            self.gfx_pipeline.handle(),
+            self.framebuffers[0],
+            self.render_pass,
+            self.target.extent,
+            self.triangle.vertex_buffer(),
+        )?;
+
+        let mut frame = FrameContext {
+            device,
+            command_buffer: frame_commands.command_buffer,
+            extent: self.target.extent,
+            frame_index: 0,
+            image_index: 0,
+            is_exit_requested: false,
+        };
+        render(&mut frame);
+
+        end_frame_commands(device, frame_commands)?;
+
+        let command_buffers = [frame_commands.command_buffer];
+        let submit_infos = [*vk::SubmitInfo::builder().command_buffers(&command_buffers)];
+
+        unsafe {
+            device
+                .queue_submit(
+                    self.context.graphics_queue,
+                    &submit_infos,
+                    self.render_fence,
+                )
+                .context("vkQueueSubmit", "headless frame")?;
+
+            device
+                .wait_for_fences(&[self.render_fence], true, u64::MAX)
+                .context("vkWaitForFences", "render fence")?;
+
+            device
+                .reset_fences(&[self.render_fence])
+                .context("vkResetFences", "render fence")?;
+        }
+        // the render pass leaves the image ready to be copied from
+        self.has_rendered_frame = true;
+
+        self.context.check_validation()
+    }
+
+    /// Copies the last rendered frame back to the CPU, failing if there hasn't been one.
+    pub fn read_frame(&self) -> Result<FrameCapture> {
+        if !self.has_rendered_frame {
+            return Err(AntithesisError::NoFrameRendered);
+        }
+
+        let capture = read_image(
+            &self.context,
+            self.command_pool,
+            self.target.image,
+            self.target.format,
+            self.target.extent,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        )?;
+
+        // the copy's barriers can trip validation too
+        self.context.check_validation()?;
+        Ok(capture)
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.target.extent
+    }
+
+    /// What pipelines for `render_frame` have to be built for.
+    pub fn render_pass(&self) -> vk::RenderPass {
+        self.render_pass
+    }
+
+    /// For creating resources like meshes to render with; they have to be destroyed before the
+    /// renderer is dropped.
+    pub fn context(&self) -> &VulkanContext {
+        &self.context
+    }
+}
+
+/// Whatever `Renderer::with_config` created so far, so a failure halfway can clean up after
+/// itself.
+#[derive(Default)]
+struct RendererParts {
+    target: Option<OffscreenTarget>,
+    depth_target: Option<OffscreenTarget>,
+    render_pass: Option<vk::RenderPass>,
+    pipeline: Option<(PipelineLayout, Pipeline)>,
+    framebuffers: Vec<vk::Framebuffer>,
+    command_pool: Option<vk::CommandPool>,
+    triangle: Option<Mesh>,
+    frame_commands: Vec<FrameCommands>,
+    render_fence: Option<vk::Fence>,
+}
+
+impl RendererParts {
+    fn create(&mut self, context: &VulkanContext, extent: vk::Extent2D) -> Result<()> {
+        let device = &context.device;
+
+        let target =
+            self.target
+                .insert(create_offscreen_target(context, OFFSCREEN_FORMAT, extent)?);
+
+        if let Some(depth_format) = context.depth_format() {
+            self.depth_target = Some(create_depth_target(context, depth_format, extent)?);
+        }
+
+        // the image gets copied out after the pass rather than presented
+        let render_pass = *self.render_pass.insert(create_render_pass(
+            device,
+            &target.format,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            context.depth_format(),
+        )?);
+
+        self.pipeline = Some(create_gfx_pipeline(
+            context,
+            render_pass,
+            &triangle_shaders()?,
+        )?);
+
+        self.framebuffers = create_framebuffers(
+            device,
+            render_pass,
+            &[target.image_view],
+            self.depth_target.as_ref().map(|depth| depth.image_view),
+            &target.extent,
+        )?;
+
+        self.command_pool = Some(create_command_pool(device, &context.queue_families)?);
+
+        self.triangle = Some(create_triangle_mesh(context)?);
+
+        self.frame_commands =
+            create_frame_commands(device, &context.queue_families, context.frames_in_flight())?;
+
+        self.render_fence = Some(unsafe {
+            device
+                .create_fence(&vk::FenceCreateInfo::builder(), None)
+                .context("vkCreateFence", "render fence")?
+        });
+
+        Ok(())
+    }
+
+    /// In the reverse order of `create`.
+    fn destroy(self, context: &VulkanContext) {
+        let device = &context.device;
+
+        if let Some(render_fence) = self.render_fence {
+            unsafe { device.destroy_fence(render_fence, None) };
+        }
+        destroy_frame_commands(device, &self.frame_commands);
+        if let Some(triangle) = self.triangle {
+            triangle.destroy(context);
+        }
+        unsafe {
+            if let Some(command_pool) = self.command_pool {
+                device.destroy_command_pool(command_pool, None);
+            }
+            for &framebuffer in self.framebuffers.iter() {
+                device.destroy_framebuffer(framebuffer, None);
+            }
+        }
+        if let Some((pipeline_layout, gfx_pipeline)) = self.pipeline {
+            gfx_pipeline.destroy(context);
+            pipeline_layout.destroy(context);
+        }
+        unsafe {
+            if let Some(render_pass) = self.render_pass {
+                device.destroy_render_pass(render_pass, None);
+            }
+        }
+        if let Some(depth_target) = self.depth_target {
+            destroy_offscreen_target(context, depth_target);
+        }
+        if let Some(target) = self.target {
+            destroy_offscreen_target(context, target);
+        }
+    }
+}
+
+impl Drop for Renderer {
+    fn drop(&mut self) {
+        let device = &self.context.device;
+
+        unsafe {
+            // nothing sensible to do if this fails, tear down regardless
+            let _ = device.device_wait_idle();
+
+            device.destroy_fence(self.render_fence, None);
+
+            destroy_frame_commands(device, &self.frame_commands);
+            device.destroy_command_pool(self.command_pool, None);
+
+            for &framebuffer in self.framebuffers.iter() {
+                device.destroy_framebuffer(framebuffer, None);
+            }
+            device.destroy_render_pass(self.render_pass, None);
+        }
+
+        self.gfx_pipeline.destroy(&self.context);
+        self.pipeline_layout.destroy(&self.context);
+
+        // SAFETY: neither is used again, the renderer is going away
+        let (triangle, target) = unsafe {
+            (
+                ManuallyDrop::take(&mut self.triangle),
+                ManuallyDrop::take(&mut self.target),
+            )
+        };
+        triangle.destroy(&self.context);
+        if let Some(depth_target) = self.depth_target.take() {
+            destroy_offscreen_target(&self.context, depth_target);
+        }
+        destroy_offscreen_target(&self.context, target);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_empty_images() {
+        // checked before any Vulkan setup, so this runs without a device
+        assert!(matches!(
+            Renderer::new(0, 64),
+            Err(AntithesisError::EmptyImage {
+                width: 0,
+                height: 64
+            })
+        ));
+    }
+}
@@ -0,0 +1,100 @@
+use std::path::{Path, PathBuf};
+
+use crate::{error::Result, shader::Spirv};
+
+/// A shader that changed on disk and compiled, handed to `Game::shaders_reloaded`.
+#[derive(Debug, Clone)]
+pub struct ReloadedShader {
+    pub path: PathBuf,
+    pub spirv: Spirv,
+}
+
+impl ReloadedShader {
+    /// Whether this is the shader called `file_name`, like `"croak.frag"`.
+    pub fn is(&self, file_name: &str) -> bool {
+        self.path.file_name() == Some(file_name.as_ref())
+    }
+}
+
+/// Watches a directory of GLSL and WGSL shaders, recompiling the ones that change.
+pub struct ShaderWatcher {
+    watcher: antithesis_watch::ShaderWatcher,
+}
+
+impl ShaderWatcher {
+    pub fn new(dir: impl AsRef<Path>) -> Result<Self> {
+        Ok(ShaderWatcher {
+            watcher: antithesis_watch::ShaderWatcher::new(dir.as_ref())?,
+        })
+    }
+
+    /// The shaders that changed since the last call and compiled. Errors are logged and the
+    /// shader left out, so whatever uses its previous version keeps working until it's fixed.
+    pub fn poll(&self) -> Vec<ReloadedShader> {
+        self.watcher
+            .poll()
+            .into_iter()
+            .filter_map(|(path, words)| {
+                let spirv = words.and_then(|words| {
+                    Spirv::from_words(words).map_err(|err| format!("{}: {}", path.display(), err))
+                });
+
+                match spirv {
+                    Ok(spirv) => {
+                        tracing::info!(path = %path.display(), "recompiled shader");
+                        Some(ReloadedShader { path, spirv })
+                    }
+                    Err(diagnostic) => {
+                        tracing::error!("failed to recompile shader\n{}", diagnostic);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        thread::sleep,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+
+    // depends on how quickly the OS delivers file events, so only run on request
+    #[test]
+    #[ignore]
+    fn picks_up_changed_shaders() {
+        let dir =
+            std::env::temp_dir().join(format!("antithesis-hot-reload-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let watcher = ShaderWatcher::new(&dir).unwrap();
+
+        fs::write(dir.join("notes.txt"), "not a shader").unwrap();
+        fs::write(
+            dir.join("broken.frag"),
+            "#version 450\nvoid main() { nope; }\n",
+        )
+        .unwrap();
+        fs::copy(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/croak.frag"),
+            dir.join("croak.frag"),
+        )
+        .unwrap();
+
+        // events arrive on the watcher's own thread
+        let mut reloaded = vec![];
+        let start = Instant::now();
+        while reloaded.is_empty() && start.elapsed() < Duration::from_secs(5) {
+            sleep(Duration::from_millis(50));
+            reloaded.extend(watcher.poll());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(!reloaded.is_empty());
+        assert!(reloaded.iter().all(|shader| shader.is("croak.frag")));
+    }
+}
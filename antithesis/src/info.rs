@@ -0,0 +1,411 @@
+use std::fmt;
+
+use ash::{vk, Entry};
+use serde::Serialize;
+use winit::window::Window;
+
+use crate::{
+    app::SurfaceInfo,
+    config::AppConfig,
+    context::create_instance,
+    device::{device_uuid, find_queue_family, vk_to_string},
+    error::{Result, VkResultExt},
+    swapchain::SwapChainSupportDetail,
+};
+
+/// Everything Vulkan reports about this machine, for attaching to bug reports.
+///
+/// Prints as indented text, or serializes to JSON with serde.
+#[derive(Debug, Clone, Serialize)]
+pub struct SystemInfo {
+    pub instance_version: String,
+    pub layers: Vec<LayerInfo>,
+    pub instance_extensions: Vec<ExtensionInfo>,
+    pub devices: Vec<DeviceInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LayerInfo {
+    pub name: String,
+    pub spec_version: String,
+    pub implementation_version: u32,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtensionInfo {
+    pub name: String,
+    pub spec_version: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceInfo {
+    pub index: usize,
+    pub name: String,
+    pub device_type: String,
+    pub api_version: String,
+    pub driver_version: u32,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    /// Only known with Vulkan 1.1.
+    pub uuid: Option<String>,
+    pub queue_families: Vec<QueueFamilyInfo>,
+    /// The families the engine would pick.
+    pub graphics_family: Option<u32>,
+    pub present_family: Option<u32>,
+    pub transfer_family: Option<u32>,
+    pub compute_family: Option<u32>,
+    pub memory_heaps: Vec<MemoryHeapInfo>,
+    pub memory_types: Vec<MemoryTypeInfo>,
+    pub extensions: Vec<ExtensionInfo>,
+    /// Only known when there was a window to ask about.
+    pub surface: Option<SurfaceSupportInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueFamilyInfo {
+    pub index: u32,
+    pub queue_count: u32,
+    pub flags: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MemoryHeapInfo {
+    pub index: u32,
+    pub size: u64,
+    pub flags: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MemoryTypeInfo {
+    pub index: u32,
+    pub heap_index: u32,
+    pub flags: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SurfaceSupportInfo {
+    pub min_image_count: u32,
+    /// Zero means there's no limit.
+    pub max_image_count: u32,
+    pub formats: Vec<String>,
+    pub present_modes: Vec<String>,
+}
+
+impl SystemInfo {
+    /// Queries the loader and every device, including surface support if given a `window`.
+    pub fn collect(window: Option<&Window>) -> Result<Self> {
+        let entry = unsafe { Entry::load()? };
+
+        let instance_version = entry
+            .try_enumerate_instance_version()
+            .context("vkEnumerateInstanceVersion", "instance")?
+            .unwrap_or(vk::API_VERSION_1_0);
+
+        let layers = entry
+            .enumerate_instance_layer_properties()
+            .context("vkEnumerateInstanceLayerProperties", "instance")?
+            .iter()
+            .map(|layer| LayerInfo {
+                name: vk_to_string(&layer.layer_name),
+                spec_version: version_to_string(layer.spec_version),
+                implementation_version: layer.implementation_version,
+                description: vk_to_string(&layer.description),
+            })
+            .collect();
+
+        let instance_extensions = entry
+            .enumerate_instance_extension_properties(None)
+            .context("vkEnumerateInstanceExtensionProperties", "instance")?
+            .iter()
+            .map(to_extension_info)
+            .collect();
+
+        // 1.1 where possible, for device UUIDs
+        let config = AppConfig::new().validation(false).api_version(
+            1,
+            vk::api_version_minor(instance_version.min(vk::API_VERSION_1_1)),
+        );
+        let (instance, _) = create_instance(window, &entry, &config)?;
+
+        let surface_info = match window.map(|window| SurfaceInfo::create(window, &entry, &instance))
+        {
+            Some(Ok(surface_info)) => Some(surface_info),
+            Some(Err(err)) => {
+                unsafe { instance.destroy_instance(None) };
+                return Err(err);
+            }
+            None => None,
+        };
+
+        let devices = collect_devices(&instance, surface_info.as_ref(), &config);
+
+        unsafe {
+            if let Some(surface_info) = &surface_info {
+                surface_info
+                    .surface_loader
+                    .destroy_surface(surface_info.surface, None);
+            }
+            instance.destroy_instance(None);
+        }
+
+        Ok(SystemInfo {
+            instance_version: version_to_string(instance_version),
+            layers,
+            instance_extensions,
+            devices: devices?,
+        })
+    }
+}
+
+fn collect_devices(
+    instance: &ash::Instance,
+    surface_info: Option<&SurfaceInfo>,
+    config: &AppConfig,
+) -> Result<Vec<DeviceInfo>> {
+    let physical_devices = unsafe {
+        instance
+            .enumerate_physical_devices()
+            .context("vkEnumeratePhysicalDevices", "instance")?
+    };
+
+    physical_devices
+        .iter()
+        .enumerate()
+        .map(|(index, &physical_device)| {
+            collect_device(instance, physical_device, index, surface_info, config)
+        })
+        .collect()
+}
+
+fn collect_device(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    index: usize,
+    surface_info: Option<&SurfaceInfo>,
+    config: &AppConfig,
+) -> Result<DeviceInfo> {
+    let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+    let memory_properties =
+        unsafe { instance.get_physical_device_memory_properties(physical_device) };
+
+    let queue_families =
+        unsafe { instance.get_physical_device_queue_family_properties(physical_device) }
+            .iter()
+            .zip(0..)
+            .map(|(queue_family, index)| QueueFamilyInfo {
+                index,
+                queue_count: queue_family.queue_count,
+                flags: format!("{:?}", queue_family.queue_flags),
+            })
+            .collect();
+    let queue_family_indices = find_queue_family(instance, physical_device, surface_info)?;
+
+    let memory_heaps = memory_properties.memory_heaps
+        [..memory_properties.memory_heap_count as usize]
+        .iter()
+        .zip(0..)
+        .map(|(heap, index)| MemoryHeapInfo {
+            index,
+            size: heap.size,
+            flags: format!("{:?}", heap.flags),
+        })
+        .collect();
+    let memory_types = memory_properties.memory_types
+        [..memory_properties.memory_type_count as usize]
+        .iter()
+        .zip(0..)
+        .map(|(memory_type, index)| MemoryTypeInfo {
+            index,
+            heap_index: memory_type.heap_index,
+            flags: format!("{:?}", memory_type.property_flags),
+        })
+        .collect();
+
+    let extensions = unsafe {
+        instance
+            .enumerate_device_extension_properties(physical_device)
+            .context("vkEnumerateDeviceExtensionProperties", "physical device")?
+    }
+    .iter()
+    .map(to_extension_info)
+    .collect();
+
+    let surface = match surface_info {
+        Some(surface_info) => {
+            let support = SwapChainSupportDetail::query(&physical_device, surface_info)?;
+            Some(SurfaceSupportInfo {
+                min_image_count: support.capabilities.min_image_count,
+                max_image_count: support.capabilities.max_image_count,
+                formats: support
+                    .formats
+                    .iter()
+                    .map(|format| format!("{:?} {:?}", format.format, format.color_space))
+                    .collect(),
+                present_modes: support
+                    .present_modes
+                    .iter()
+                    .map(|present_mode| format!("{:?}", present_mode))
+                    .collect(),
+            })
+        }
+        None => None,
+    };
+
+    Ok(DeviceInfo {
+        index,
+        name: vk_to_string(&properties.device_name),
+        device_type: format!("{:?}", properties.device_type),
+        api_version: version_to_string(properties.api_version),
+        driver_version: properties.driver_version,
+        vendor_id: properties.vendor_id,
+        device_id: properties.device_id,
+        uuid: device_uuid(instance, physical_device, &properties, config),
+        queue_families,
+        graphics_family: queue_family_indices.graphics_family,
+        present_family: queue_family_indices.present_family,
+        transfer_family: queue_family_indices.transfer_family,
+        compute_family: queue_family_indices.compute_family,
+        memory_heaps,
+        memory_types,
+        extensions,
+        surface,
+    })
+}
+
+fn to_extension_info(extension: &vk::ExtensionProperties) -> ExtensionInfo {
+    ExtensionInfo {
+        name: vk_to_string(&extension.extension_name),
+        spec_version: extension.spec_version,
+    }
+}
+
+fn version_to_string(version: u32) -> String {
+    format!(
+        "{}.{}.{}",
+        vk::api_version_major(version),
+        vk::api_version_minor(version),
+        vk::api_version_patch(version)
+    )
+}
+
+impl fmt::Display for SystemInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Vulkan instance {}", self.instance_version)?;
+
+        writeln!(f, "Layers:")?;
+        for layer in &self.layers {
+            writeln!(
+                f,
+                "    {} ({}): {}",
+                layer.name, layer.spec_version, layer.description
+            )?;
+        }
+
+        writeln!(f, "Instance extensions:")?;
+        for extension in &self.instance_extensions {
+            writeln!(f, "    {} (rev {})", extension.name, extension.spec_version)?;
+        }
+
+        for device in &self.devices {
+            writeln!(f)?;
+            write!(f, "{}", device)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "GPU {}: {} ({})",
+            self.index, self.name, self.device_type
+        )?;
+        writeln!(
+            f,
+            "    API {}, driver {:#x}, vendor {:#06x}, device {:#06x}",
+            self.api_version, self.driver_version, self.vendor_id, self.device_id
+        )?;
+        writeln!(
+            f,
+            "    UUID {}",
+            self.uuid.as_deref().unwrap_or("unknown (needs Vulkan 1.1)")
+        )?;
+
+        let describe_family = |family: Option<u32>| match family {
+            Some(family) => family.to_string(),
+            None => "none".to_owned(),
+        };
+        writeln!(
+            f,
+            "    Queue families (graphics {}, present {}, transfer {}, compute {}):",
+            describe_family(self.graphics_family),
+            describe_family(self.present_family),
+            describe_family(self.transfer_family),
+            describe_family(self.compute_family)
+        )?;
+        for queue_family in &self.queue_families {
+            writeln!(
+                f,
+                "        {}: {}x {}",
+                queue_family.index, queue_family.queue_count, queue_family.flags
+            )?;
+        }
+
+        writeln!(f, "    Memory heaps:")?;
+        for heap in &self.memory_heaps {
+            writeln!(
+                f,
+                "        {}: {} MiB {}",
+                heap.index,
+                heap.size >> 20,
+                heap.flags
+            )?;
+        }
+
+        writeln!(f, "    Memory types:")?;
+        for memory_type in &self.memory_types {
+            writeln!(
+                f,
+                "        {}: heap {} {}",
+                memory_type.index, memory_type.heap_index, memory_type.flags
+            )?;
+        }
+
+        writeln!(f, "    Extensions:")?;
+        for extension in &self.extensions {
+            writeln!(
+                f,
+                "        {} (rev {})",
+                extension.name, extension.spec_version
+            )?;
+        }
+
+        match &self.surface {
+            Some(surface) => {
+                writeln!(
+                    f,
+                    "    Surface images: {} to {}",
+                    surface.min_image_count,
+                    match surface.max_image_count {
+                        0 => "unlimited".to_owned(),
+                        max_image_count => max_image_count.to_string(),
+                    }
+                )?;
+                writeln!(f, "    Surface formats:")?;
+                for format in &surface.formats {
+                    writeln!(f, "        {}", format)?;
+                }
+                writeln!(f, "    Present modes:")?;
+                for present_mode in &surface.present_modes {
+                    writeln!(f, "        {}", present_mode)?;
+                }
+            }
+            None => writeln!(f, "    Surface: not queried (no window)")?,
+        }
+
+        Ok(())
+    }
+}
@@ -1,5 +1,32 @@
+// lets the derive macros refer to `::antithesis` from inside this crate too
+extern crate self as antithesis;
+
+pub mod allocator;
 pub mod app;
+pub mod config;
+pub mod context;
+mod debug;
+pub mod descriptor;
 mod device;
-mod pipeline;
+pub mod error;
+pub mod features;
+pub mod frame;
+pub mod game;
+pub mod headless;
+#[cfg(feature = "hot-reload")]
+pub mod hot_reload;
+pub mod info;
+pub mod mesh;
+mod offscreen;
+pub mod pipeline;
+pub mod pod;
+pub mod readback;
+pub mod shader;
+#[cfg(feature = "compile-shaders")]
+pub mod shaders;
 mod swapchain;
 mod sync;
+pub mod upload;
+
+// vertex layouts and other public types are made of `vk` structs
+pub use ash;
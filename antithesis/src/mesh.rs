@@ -0,0 +1,232 @@
+use ash::vk;
+
+pub use antithesis_derive::VertexLayout;
+
+use crate::{
+    context::VulkanContext,
+    error::{AntithesisError, Result},
+    pod::Pod,
+    upload::{upload_buffer, DeviceBuffer},
+};
+
+/// How a vertex type is laid out in a vertex buffer, as the pipeline's vertex input state
+/// wants it.
+#[derive(Debug, Clone, Default)]
+pub struct VertexLayout {
+    pub bindings: Vec<vk::VertexInputBindingDescription>,
+    pub attributes: Vec<vk::VertexInputAttributeDescription>,
+}
+
+impl VertexLayout {
+    /// Adds `other`'s bindings and attributes, like a per-instance buffer next to the vertices.
+    pub fn with(mut self, other: VertexLayout) -> Self {
+        self.bindings.extend(other.bindings);
+        self.attributes.extend(other.attributes);
+        self
+    }
+}
+
+/// A `#[repr(C)]` struct that can go in a vertex buffer as is, usually implemented with
+/// `#[derive(VertexLayout)]`. Fields can't share a location:
+///
+/// ```compile_fail
+/// use antithesis::{mesh::VertexLayout, pod::Pod};
+///
+/// #[repr(C)]
+/// #[derive(Clone, Copy, Pod, VertexLayout)]
+/// struct SkinnedVertex {
+///     pos: [f32; 3],
+///     #[location(0)]
+///     weights: [f32; 4],
+/// }
+/// ```
+pub trait Vertex: Pod {
+    fn layout() -> VertexLayout;
+}
+
+/// Index data for an indexed `Mesh`; 16 bit indices take half the memory but only reach
+/// 65536 vertices.
+#[derive(Debug, Clone, Copy)]
+pub enum Indices<'a> {
+    U16(&'a [u16]),
+    U32(&'a [u32]),
+}
+
+impl Indices<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Indices::U16(indices) => indices.len(),
+            Indices::U32(indices) => indices.len(),
+        }
+    }
+
+    fn index_type(&self) -> vk::IndexType {
+        match self {
+            Indices::U16(_) => vk::IndexType::UINT16,
+            Indices::U32(_) => vk::IndexType::UINT32,
+        }
+    }
+
+    fn upload(&self, context: &VulkanContext) -> Result<DeviceBuffer> {
+        let usage = vk::BufferUsageFlags::INDEX_BUFFER;
+        match self {
+            Indices::U16(indices) => upload_buffer(context, indices, usage),
+            Indices::U32(indices) => upload_buffer(context, indices, usage),
+        }
+    }
+}
+
+impl<'a> From<&'a [u16]> for Indices<'a> {
+    fn from(indices: &'a [u16]) -> Self {
+        Indices::U16(indices)
+    }
+}
+
+impl<'a> From<&'a [u32]> for Indices<'a> {
+    fn from(indices: &'a [u32]) -> Self {
+        Indices::U32(indices)
+    }
+}
+
+impl<'a, const N: usize> From<&'a [u16; N]> for Indices<'a> {
+    fn from(indices: &'a [u16; N]) -> Self {
+        Indices::U16(indices)
+    }
+}
+
+impl<'a, const N: usize> From<&'a [u32; N]> for Indices<'a> {
+    fn from(indices: &'a [u32; N]) -> Self {
+        Indices::U32(indices)
+    }
+}
+
+struct IndexBuffer {
+    buffer: DeviceBuffer,
+    index_type: vk::IndexType,
+    index_count: u32,
+}
+
+/// Vertices and optionally indices in device local memory, drawn with `FrameContext::draw_mesh`.
+///
+/// Has to be destroyed with `destroy` while the context is still around, once the GPU is done
+/// with it (for instance in `Game::exit`).
+pub struct Mesh {
+    vertex_buffer: DeviceBuffer,
+    vertex_count: u32,
+    index_buffer: Option<IndexBuffer>,
+    layout: VertexLayout,
+}
+
+impl Mesh {
+    /// A mesh drawn straight from `vertices`, every three making up a triangle. Errors if
+    /// there are no vertices.
+    pub fn new<V: Vertex>(context: &VulkanContext, vertices: &[V]) -> Result<Self> {
+        if vertices.is_empty() {
+            return Err(AntithesisError::EmptyBuffer("vertex buffer"));
+        }
+
+        let vertex_buffer = upload_buffer(context, vertices, vk::BufferUsageFlags::VERTEX_BUFFER)?;
+
+        Ok(Mesh {
+            vertex_buffer,
+            vertex_count: vertices.len() as u32,
+            index_buffer: None,
+            layout: V::layout(),
+        })
+    }
+
+    /// A mesh drawn through `indices`, so shared vertices only have to be stored once. Errors if
+    /// there are no vertices or no indices.
+    pub fn indexed<'a, V: Vertex>(
+        context: &VulkanContext,
+        vertices: &[V],
+        indices: impl Into<Indices<'a>>,
+    ) -> Result<Self> {
+        let indices = indices.into();
+        if indices.len() == 0 {
+            return Err(AntithesisError::EmptyBuffer("index buffer"));
+        }
+
+        let mut mesh = Self::new(context, vertices)?;
+
+        let buffer = match indices.upload(context) {
+            Ok(buffer) => buffer,
+            Err(err) => {
+                mesh.destroy(context);
+                return Err(err);
+            }
+        };
+
+        mesh.index_buffer = Some(IndexBuffer {
+            buffer,
+            index_type: indices.index_type(),
+            index_count: indices.len() as u32,
+        });
+
+        Ok(mesh)
+    }
+
+    pub fn vertex_count(&self) -> u32 {
+        self.vertex_count
+    }
+
+    /// Zero for meshes without indices.
+    pub fn index_count(&self) -> u32 {
+        self.index_buffer
+            .as_ref()
+            .map_or(0, |index_buffer| index_buffer.index_count)
+    }
+
+    pub fn is_indexed(&self) -> bool {
+        self.index_buffer.is_some()
+    }
+
+    /// The layout of the vertices, which the pipeline drawing the mesh has to match.
+    pub fn layout(&self) -> &VertexLayout {
+        &self.layout
+    }
+
+    pub fn vertex_buffer(&self) -> vk::Buffer {
+        self.vertex_buffer.handle()
+    }
+
+    /// Frees the mesh's buffers; the GPU must not be using them anymore.
+    pub fn destroy(self, context: &VulkanContext) {
+        self.vertex_buffer.destroy(context);
+        if let Some(index_buffer) = self.index_buffer {
+            index_buffer.buffer.destroy(context);
+        }
+    }
+
+    /// Binds the mesh's buffers and draws it `instance_count` times.
+    pub(crate) fn record_draw(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        instance_count: u32,
+    ) {
+        unsafe {
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[self.vertex_buffer.handle()], &[0]);
+
+            match &self.index_buffer {
+                Some(index_buffer) => {
+                    device.cmd_bind_index_buffer(
+                        command_buffer,
+                        index_buffer.buffer.handle(),
+                        0,
+                        index_buffer.index_type,
+                    );
+                    device.cmd_draw_indexed(
+                        command_buffer,
+                        index_buffer.index_count,
+                        instance_count,
+                        0,
+                        0,
+                        0,
+                    );
+                }
+                None => device.cmd_draw(command_buffer, self.vertex_count, instance_count, 0, 0),
+            }
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use ash::vk;
+
+use crate::{
+    allocator::{Allocation, AllocationInfo, MemoryLocation, ResourceKind},
+    context::VulkanContext,
+    error::{Result, VkResultExt},
+    swapchain::create_image_view,
+};
+
+// same format the swapchain prefers, so both modes go down the same path
+pub const OFFSCREEN_FORMAT: vk::Format = vk::Format::B8G8R8A8_SRGB;
+
+// the spec guarantees depth attachment support for one of the first two
+const DEPTH_FORMATS: [vk::Format; 4] = [
+    vk::Format::D32_SFLOAT,
+    vk::Format::X8_D24_UNORM_PACK32,
+    vk::Format::D24_UNORM_S8_UINT,
+    vk::Format::D32_SFLOAT_S8_UINT,
+];
+
+/// An engine-owned image to render into: the color image standing in for the swapchain when
+/// rendering headlessly, or a depth buffer.
+pub struct OffscreenTarget {
+    pub image: vk::Image,
+    pub image_allocation: Allocation,
+    pub image_view: vk::ImageView,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+}
+
+pub fn create_offscreen_target(
+    context: &VulkanContext,
+    format: vk::Format,
+    extent: vk::Extent2D,
+) -> Result<OffscreenTarget> {
+    // rendered to, then copied out
+    create_target(
+        context,
+        format,
+        extent,
+        vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+        vk::ImageAspectFlags::COLOR,
+    )
+}
+
+/// A depth buffer for render passes with a depth attachment of `format`, see
+/// `find_depth_format`.
+pub fn create_depth_target(
+    context: &VulkanContext,
+    format: vk::Format,
+    extent: vk::Extent2D,
+) -> Result<OffscreenTarget> {
+    create_target(
+        context,
+        format,
+        extent,
+        vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        vk::ImageAspectFlags::DEPTH,
+    )
+}
+
+/// The first depth format `physical_device` can render to with optimal tiling.
+pub(crate) fn find_depth_format(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+) -> vk::Format {
+    DEPTH_FORMATS
+        .into_iter()
+        .find(|&format| {
+            let properties =
+                unsafe { instance.get_physical_device_format_properties(physical_device, format) };
+            properties
+                .optimal_tiling_features
+                .contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+        })
+        .expect("every Vulkan device supports a depth format")
+}
+
+fn create_target(
+    context: &VulkanContext,
+    format: vk::Format,
+    extent: vk::Extent2D,
+    usage: vk::ImageUsageFlags,
+    aspect_flags: vk::ImageAspectFlags,
+) -> Result<OffscreenTarget> {
+    let device = &context.device;
+
+    let image_create_info = vk::ImageCreateInfo::builder()
+        .image_type(vk::ImageType::TYPE_2D)
+        .format(format)
+        .extent(vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        })
+        .mip_levels(1)
+        .array_layers(1)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .usage(usage)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE)
+        .initial_layout(vk::ImageLayout::UNDEFINED);
+
+    let image = unsafe {
+        device
+            .create_image(&image_create_info, None)
+            .context("vkCreateImage", "offscreen image")?
+    };
+
+    // render targets are big and live as long as the renderer, so they get their own memory
+    let allocation_info = AllocationInfo {
+        requirements: unsafe { device.get_image_memory_requirements(image) },
+        location: MemoryLocation::GpuOnly,
+        kind: ResourceKind::Optimal,
+        is_dedicated: true,
+    };
+
+    let image_allocation = context.allocator().allocate(&allocation_info);
+    let image_allocation = match image_allocation {
+        Ok(image_allocation) => image_allocation,
+        Err(err) => {
+            unsafe { device.destroy_image(image, None) };
+            return Err(err);
+        }
+    };
+
+    let image_view = unsafe {
+        device
+            .bind_image_memory(image, image_allocation.memory(), image_allocation.offset())
+            .context("vkBindImageMemory", "offscreen image memory")
+    }
+    .and_then(|_| create_image_view(device, image, format, aspect_flags, 1));
+
+    let image_view = match image_view {
+        Ok(image_view) => image_view,
+        Err(err) => {
+            unsafe { device.destroy_image(image, None) };
+            context.allocator().free(image_allocation);
+            return Err(err);
+        }
+    };
+
+    Ok(OffscreenTarget {
+        image,
+        image_allocation,
+        image_view,
+        format,
+        extent,
+    })
+}
+
+pub fn destroy_offscreen_target(context: &VulkanContext, target: OffscreenTarget) {
+    unsafe {
+        context.device.destroy_image_view(target.image_view, None);
+        context.device.destroy_image(target.image, None);
+    }
+    context.allocator().free(target.image_allocation);
+}
@@ -1,137 +1,232 @@
-use std::{ffi::CString, io::Cursor};
-
-use ash::{
-    util::read_spv,
-    vk
+use std::ffi::CStr;
+
+use ash::vk;
+
+use crate::{
+    allocator::{Allocation, AllocationInfo, MemoryLocation, ResourceKind},
+    context::VulkanContext,
+    descriptor::DescriptorSetLayout,
+    error::{AntithesisError, Result, VkResultExt},
+    mesh::{Mesh, Vertex, VertexLayout},
+    pod::Pod,
+    shader::Spirv,
 };
-use memoffset::offset_of;
 
 // hardcoded
-const VERTICES_DATA: [Vertex; 3] = [
-    Vertex {
+const VERTICES_DATA: [ColoredVertex; 3] = [
+    ColoredVertex {
         pos: [0.0, -0.5],
         color: [1.0, 0.0, 0.0],
     },
-    Vertex {
+    ColoredVertex {
         pos: [0.5, 0.5],
         color: [0.0, 1.0, 0.0],
     },
-    Vertex {
+    ColoredVertex {
         pos: [-0.5, 0.5],
         color: [0.0, 0.0, 1.0],
     },
 ];
 
 #[repr(C)]
-#[derive(Debug, Clone)]
-struct Vertex {
+#[derive(Debug, Clone, Copy, Pod, VertexLayout)]
+struct ColoredVertex {
     pos: [f32; 2],
     color: [f32; 3],
 }
 
-impl Vertex {
-    fn get_binding_descriptions() -> [vk::VertexInputBindingDescription; 1] {
-        [*vk::VertexInputBindingDescription::builder()
-            .binding(0)
-            .stride(std::mem::size_of::<Self>() as u32)
-            .input_rate(vk::VertexInputRate::VERTEX)]
-    }
-
-    fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescription; 2] {
-        [
-            *vk::VertexInputAttributeDescription::builder()
-                .binding(0)
-                .location(0)
-                .format(vk::Format::R32G32_SFLOAT)
-                .offset(offset_of!(Self, pos) as u32),
-            *vk::VertexInputAttributeDescription::builder()
-                .binding(0)
-                .location(1)
-                .format(vk::Format::R32G32B32_SFLOAT)
-                .offset(offset_of!(Self, color) as u32),
-        ]
-    }
+/// The built-in triangle, bound by default in every frame.
+pub(crate) fn create_triangle_mesh(context: &VulkanContext) -> Result<Mesh> {
+    Mesh::new(context, &VERTICES_DATA)
 }
 
-pub fn create_vertex_buffer(
-    device: &ash::Device,
-    physical_device: vk::PhysicalDevice,
-    instance: &ash::Instance,
-) -> (vk::Buffer, vk::DeviceMemory) {
-    let vertex_buffer_create_info = vk::BufferCreateInfo::builder()
-        .size(std::mem::size_of_val(&VERTICES_DATA) as u64)
-        .usage(vk::BufferUsageFlags::VERTEX_BUFFER)
+/// Creates a buffer with memory from the context's allocator, already bound.
+pub(crate) fn create_buffer(
+    context: &VulkanContext,
+    size: vk::DeviceSize,
+    usage: vk::BufferUsageFlags,
+    location: MemoryLocation,
+) -> Result<(vk::Buffer, Allocation)> {
+    let device = &context.device;
+
+    let buffer_create_info = vk::BufferCreateInfo::builder()
+        .size(size)
+        .usage(usage)
         .sharing_mode(vk::SharingMode::EXCLUSIVE);
 
-    let vertex_buffer = unsafe {
+    let buffer = unsafe {
         device
-            .create_buffer(&vertex_buffer_create_info, None)
-            .expect("Failed to create the vertex buffer!")
+            .create_buffer(&buffer_create_info, None)
+            .context("vkCreateBuffer", "buffer")?
     };
 
-    let mem_requirements = unsafe { device.get_buffer_memory_requirements(vertex_buffer) };
-    let mem_properties = unsafe { instance.get_physical_device_memory_properties(physical_device) };
-    let required_memory_flags: vk::MemoryPropertyFlags =
-        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
-    let memory_type = find_memory_type(
-        mem_requirements.memory_type_bits,
-        required_memory_flags,
-        mem_properties,
-    );
-
-    let allocate_info = vk::MemoryAllocateInfo::builder()
-        .allocation_size(mem_requirements.size)
-        .memory_type_index(memory_type);
+    let allocation_info = AllocationInfo {
+        requirements: unsafe { device.get_buffer_memory_requirements(buffer) },
+        location,
+        kind: ResourceKind::Linear,
+        is_dedicated: false,
+    };
 
-    let vertex_buffer_memory = unsafe {
-        device
-            .allocate_memory(&allocate_info, None)
-            .expect("Failed to allocate vertex buffer memory!")
+    let allocation = context.allocator().allocate(&allocation_info);
+    let allocation = match allocation {
+        Ok(allocation) => allocation,
+        Err(err) => {
+            unsafe { device.destroy_buffer(buffer, None) };
+            return Err(err);
+        }
     };
 
-    unsafe {
-        device
-            .bind_buffer_memory(vertex_buffer, vertex_buffer_memory, 0)
-            .expect("Failed to bind Buffer");
-
-        let data_ptr = device
-            .map_memory(
-                vertex_buffer_memory,
-                0,
-                vertex_buffer_create_info.size,
-                vk::MemoryMapFlags::empty(),
-            )
-            .expect("Failed to Map Memory") as *mut Vertex;
+    let result =
+        unsafe { device.bind_buffer_memory(buffer, allocation.memory(), allocation.offset()) }
+            .context("vkBindBufferMemory", "buffer memory");
+    if let Err(err) = result {
+        destroy_buffer(context, buffer, allocation);
+        return Err(err);
+    }
 
-        data_ptr.copy_from_nonoverlapping(VERTICES_DATA.as_ptr(), VERTICES_DATA.len());
+    Ok((buffer, allocation))
+}
 
-        device.unmap_memory(vertex_buffer_memory);
-    }
+pub(crate) fn destroy_buffer(context: &VulkanContext, buffer: vk::Buffer, allocation: Allocation) {
+    unsafe { context.device.destroy_buffer(buffer, None) };
+    context.allocator().free(allocation);
+}
 
-    (vertex_buffer, vertex_buffer_memory)
+/// The descriptor sets and push constants a pipeline's shaders take; pipelines with the same
+/// layout can share descriptor sets and push constants between draws.
+pub struct PipelineLayout {
+    layout: vk::PipelineLayout,
+    push_constant_ranges: Vec<vk::PushConstantRange>,
 }
 
-fn find_memory_type(
-    type_filter: u32,
-    required_properties: vk::MemoryPropertyFlags,
-    mem_properties: vk::PhysicalDeviceMemoryProperties,
-) -> u32 {
-    for (i, memory_type) in mem_properties.memory_types.iter().enumerate() {
-        //if (type_filter & (1 << i)) > 0 && (memory_type.property_flags & required_properties) == required_properties {
-        //    return i as u32
-        // }
-
-        // same implementation
-        if (type_filter & (1 << i)) > 0 && memory_type.property_flags.contains(required_properties)
-        {
-            return i as u32;
+impl PipelineLayout {
+    /// `set_layouts` go in set number order. Push constant ranges together can't exceed the
+    /// device's `maxPushConstantsSize`, which is at least 128 bytes.
+    pub fn new(
+        context: &VulkanContext,
+        set_layouts: &[&DescriptorSetLayout],
+        push_constant_ranges: &[vk::PushConstantRange],
+    ) -> Result<Self> {
+        let size = push_constant_ranges
+            .iter()
+            .map(|range| range.offset + range.size)
+            .max()
+            .unwrap_or(0);
+        let max = unsafe {
+            context
+                .instance
+                .get_physical_device_properties(context.physical_device)
         }
+        .limits
+        .max_push_constants_size;
+        if size > max {
+            return Err(AntithesisError::PushConstantsTooLarge { size, max });
+        }
+
+        let set_layouts: Vec<_> = set_layouts
+            .iter()
+            .map(|set_layout| set_layout.handle())
+            .collect();
+        let layout = create_pipeline_layout(&context.device, &set_layouts, push_constant_ranges)?;
+
+        Ok(PipelineLayout {
+            layout,
+            push_constant_ranges: push_constant_ranges.to_vec(),
+        })
+    }
+
+    pub fn handle(&self) -> vk::PipelineLayout {
+        self.layout
+    }
+
+    pub fn push_constant_ranges(&self) -> &[vk::PushConstantRange] {
+        &self.push_constant_ranges
+    }
+
+    /// Whether a push of `size` bytes at `offset` for `stages` fits in the declared ranges.
+    pub(crate) fn is_push_declared(
+        &self,
+        stages: vk::ShaderStageFlags,
+        offset: u32,
+        size: u32,
+    ) -> bool {
+        is_push_declared(&self.push_constant_ranges, stages, offset, size)
+    }
+
+    /// No pipeline made with the layout can be in use anymore.
+    pub fn destroy(&self, context: &VulkanContext) {
+        unsafe { context.device.destroy_pipeline_layout(self.layout, None) };
+    }
+}
+
+/// A push constant range big enough for a `T` at `offset`, for `PipelineLayout::new`.
+///
+/// `T` should be `#[repr(C)]` and match the shader's `push_constant` block.
+pub fn push_constant_range<T>(stages: vk::ShaderStageFlags, offset: u32) -> vk::PushConstantRange {
+    let size = std::mem::size_of::<T>() as u32;
+    assert!(
+        offset % 4 == 0 && size % 4 == 0,
+        "push constant offsets and sizes have to be multiples of 4"
+    );
+
+    vk::PushConstantRange {
+        stage_flags: stages,
+        offset,
+        size,
     }
+}
 
-    panic!("Failed to find suitable memory type!")
+/// Every pushed byte has to be in a range for each of `stages`, and the ranges it's in can't
+/// have stages that weren't pushed to.
+fn is_push_declared(
+    ranges: &[vk::PushConstantRange],
+    stages: vk::ShaderStageFlags,
+    offset: u32,
+    size: u32,
+) -> bool {
+    (offset..offset + size).all(|byte| {
+        let covering: Vec<_> = ranges
+            .iter()
+            .filter(|range| (range.offset..range.offset + range.size).contains(&byte))
+            .collect();
+        let covered_stages = covering
+            .iter()
+            .fold(vk::ShaderStageFlags::empty(), |covered, range| {
+                covered | range.stage_flags
+            });
+
+        covered_stages.contains(stages)
+            && covering
+                .iter()
+                .all(|range| stages.contains(range.stage_flags))
+    })
 }
 
-pub fn create_render_pass(device: &ash::Device, surface_format: &vk::Format) -> vk::RenderPass {
+fn create_pipeline_layout(
+    device: &ash::Device,
+    set_layouts: &[vk::DescriptorSetLayout],
+    push_constant_ranges: &[vk::PushConstantRange],
+) -> Result<vk::PipelineLayout> {
+    let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(set_layouts)
+        .push_constant_ranges(push_constant_ranges);
+
+    unsafe {
+        device
+            .create_pipeline_layout(&pipeline_layout_create_info, None)
+            .context("vkCreatePipelineLayout", "graphics pipeline layout")
+    }
+}
+
+/// `final_layout` is `PRESENT_SRC_KHR` for swapchain images and `TRANSFER_SRC_OPTIMAL` for
+/// offscreen targets that get copied out after rendering. With a `depth_format` the pass gets a
+/// depth attachment too, cleared at the start and thrown away at the end.
+pub(crate) fn create_render_pass(
+    device: &ash::Device,
+    surface_format: &vk::Format,
+    final_layout: vk::ImageLayout,
+    depth_format: Option<vk::Format>,
+) -> Result<vk::RenderPass> {
     let color_attachment = vk::AttachmentDescription::builder()
         .format(*surface_format)
         .samples(vk::SampleCountFlags::TYPE_1)
@@ -140,22 +235,62 @@ pub fn create_render_pass(device: &ash::Device, surface_format: &vk::Format) ->
         .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
         .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
         .initial_layout(vk::ImageLayout::UNDEFINED)
-        .final_layout(vk::ImageLayout::PRESENT_SRC_KHR);
+        .final_layout(final_layout);
 
     let color_attachment_ref =
         [*vk::AttachmentReference::builder().layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
 
-    let render_pass_attachments = [*color_attachment];
+    let mut render_pass_attachments = vec![*color_attachment];
 
-    let subpasses = [*vk::SubpassDescription::builder()
+    let mut subpass = vk::SubpassDescription::builder()
         .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-        .color_attachments(&color_attachment_ref)];
+        .color_attachments(&color_attachment_ref);
 
-    let subpass_dependencies = [*vk::SubpassDependency::builder()
+    let mut subpass_dependency = vk::SubpassDependency::builder()
         .src_subpass(vk::SUBPASS_EXTERNAL)
         .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
         .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-        .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)];
+        .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE);
+
+    let depth_attachment_ref = vk::AttachmentReference::builder()
+        .attachment(1)
+        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+    if let Some(depth_format) = depth_format {
+        render_pass_attachments.push(
+            *vk::AttachmentDescription::builder()
+                .format(depth_format)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL),
+        );
+
+        subpass = subpass.depth_stencil_attachment(&depth_attachment_ref);
+
+        // every frame in flight shares the one depth buffer, so clearing it has to wait for the
+        // previous frame's depth writes
+        subpass_dependency = subpass_dependency
+            .src_stage_mask(
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            )
+            .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .dst_stage_mask(
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+            )
+            .dst_access_mask(
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            );
+    }
+
+    let subpasses = [*subpass];
+    let subpass_dependencies = [*subpass_dependency];
 
     let render_pass_create_info = vk::RenderPassCreateInfo::builder()
         .attachments(&render_pass_attachments)
@@ -165,142 +300,385 @@ pub fn create_render_pass(device: &ash::Device, surface_format: &vk::Format) ->
     unsafe {
         device
             .create_render_pass(&render_pass_create_info, None)
-            .expect("Failed to create render pass!")
+            .context("vkCreateRenderPass", "render pass")
     }
 }
 
-pub fn create_gfx_pipeline(
-    device: &ash::Device,
-    render_pass: vk::RenderPass,
-    swapchain_extent: &vk::Extent2D,
-) -> (vk::PipelineLayout, vk::Pipeline) {
-    let mut vert_file = Cursor::new(&include_bytes!("../shaders/vert.spv"));
-    let mut frag_file = Cursor::new(&include_bytes!("../shaders/frag.spv"));
-
-    let vert_shader = create_shader_module(&device, &mut vert_file);
-    let frag_shader = create_shader_module(&device, &mut frag_file);
-
-    let main_function_name = CString::new("main").unwrap(); // the beginning function name in shader code.
-    let shader_stages = [
-        *vk::PipelineShaderStageCreateInfo::builder()
-            .module(vert_shader)
-            .name(&main_function_name)
-            .stage(vk::ShaderStageFlags::VERTEX),
-        *vk::PipelineShaderStageCreateInfo::builder()
-            .module(frag_shader)
-            .name(&main_function_name)
-            .stage(vk::ShaderStageFlags::FRAGMENT),
-    ];
-
-    let binding_description = Vertex::get_binding_descriptions();
-    let attribute_description = Vertex::get_attribute_descriptions();
-
-    let vertex_input_state_create_info = vk::PipelineVertexInputStateCreateInfo::builder()
-        .vertex_attribute_descriptions(&attribute_description)
-        .vertex_binding_descriptions(&binding_description);
-
-    let input_assembly_state_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
-        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
-
-    let viewports = [*vk::Viewport::builder()
-        .x(0.0)
-        .y(0.0)
-        .width(swapchain_extent.width as f32)
-        .height(swapchain_extent.height as f32)
-        .min_depth(0.0)
-        .max_depth(1.0)];
-
-    let scissors = [*vk::Rect2D::builder()
-        .offset(*vk::Offset2D::builder())
-        .extent(*swapchain_extent)];
-
-    let viewport_state_create_info = vk::PipelineViewportStateCreateInfo::builder()
-        .scissors(&scissors)
-        .viewports(&viewports);
-
-    // Maybe shouldn't be default!
-    let rasterization_state_create_info = vk::PipelineRasterizationStateCreateInfo::builder()
-        .cull_mode(vk::CullModeFlags::BACK)
-        .front_face(vk::FrontFace::CLOCKWISE)
-        .line_width(1.0)
-        .polygon_mode(vk::PolygonMode::FILL);
-
-    // Maybe also shouldn't be default
-    let multisample_state_create_info = vk::PipelineMultisampleStateCreateInfo::builder()
-        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
-
-    let stencil_state = vk::StencilOpState::builder()
-        .fail_op(vk::StencilOp::KEEP)
-        .pass_op(vk::StencilOp::KEEP)
-        .depth_fail_op(vk::StencilOp::KEEP)
-        .compare_op(vk::CompareOp::ALWAYS);
-
-    let depth_stencil_state_create_info = vk::PipelineDepthStencilStateCreateInfo::builder()
-        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
-        .front(*stencil_state)
-        .back(*stencil_state)
-        .max_depth_bounds(1.0)
-        .min_depth_bounds(0.0);
-
-    let color_blend_attachment_states = [*vk::PipelineColorBlendAttachmentState::builder()
-        .color_write_mask(vk::ColorComponentFlags::RGBA)
-        .src_color_blend_factor(vk::BlendFactor::SRC_COLOR)
-        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_DST_COLOR)
-        .color_blend_op(vk::BlendOp::ADD)
-        .src_alpha_blend_factor(vk::BlendFactor::ZERO)
-        .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
-        .alpha_blend_op(vk::BlendOp::ADD)
-        .color_write_mask(vk::ColorComponentFlags::RGBA)];
-
-    let color_blend_state_create_info = vk::PipelineColorBlendStateCreateInfo::builder()
-        .logic_op(vk::LogicOp::COPY)
-        .attachments(&color_blend_attachment_states);
-
-    let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo::builder();
-
-    let pipeline_layout = unsafe {
-        device
-            .create_pipeline_layout(&pipeline_layout_create_info, None)
-            .expect("Failed to create pipeline layout!")
-    };
+/// How a pipeline's output gets combined with what's already in the color attachment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    /// Overwrites the destination.
+    #[default]
+    Opaque,
+    /// Regular transparency, weighted by the source alpha.
+    Alpha,
+    /// Adds the source (weighted by its alpha) onto the destination, for glows and particles.
+    Additive,
+    /// Transparency for colors that have already been multiplied by their alpha.
+    Premultiplied,
+}
 
-    // dynamic state not included for now
-    let gfx_pipeline_create_info = [*vk::GraphicsPipelineCreateInfo::builder()
-        .stages(&shader_stages)
-        .vertex_input_state(&vertex_input_state_create_info)
-        .input_assembly_state(&input_assembly_state_info)
-        .viewport_state(&viewport_state_create_info)
-        .rasterization_state(&rasterization_state_create_info)
-        .multisample_state(&multisample_state_create_info)
-        .depth_stencil_state(&depth_stencil_state_create_info)
-        .color_blend_state(&color_blend_state_create_info)
-        .layout(pipeline_layout)
-        .render_pass(render_pass)];
-
-    let gfx_pipeline = unsafe {
-        device
-            .create_graphics_pipelines(vk::PipelineCache::null(), &gfx_pipeline_create_info, None)
-            .expect("Failed to create graphics pipeline!")
-    };
+impl BlendMode {
+    fn attachment_state(self) -> vk::PipelineColorBlendAttachmentState {
+        let (src_color, dst_color, src_alpha, dst_alpha) = match self {
+            BlendMode::Opaque => {
+                return vk::PipelineColorBlendAttachmentState {
+                    color_write_mask: vk::ColorComponentFlags::RGBA,
+                    ..Default::default()
+                }
+            }
+            BlendMode::Alpha => (
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            ),
+            BlendMode::Additive => (
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE,
+            ),
+            BlendMode::Premultiplied => (
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            ),
+        };
 
-    unsafe {
-        device.destroy_shader_module(vert_shader, None);
-        device.destroy_shader_module(frag_shader, None);
+        vk::PipelineColorBlendAttachmentState {
+            blend_enable: vk::TRUE,
+            src_color_blend_factor: src_color,
+            dst_color_blend_factor: dst_color,
+            color_blend_op: vk::BlendOp::ADD,
+            src_alpha_blend_factor: src_alpha,
+            dst_alpha_blend_factor: dst_alpha,
+            alpha_blend_op: vk::BlendOp::ADD,
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+        }
     }
+}
 
-    (pipeline_layout, gfx_pipeline[0])
+/// Depth testing and writing. Only does anything in render passes with a depth attachment, like
+/// the one games draw in with `AppConfig::depth_buffer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DepthState {
+    pub is_test_enabled: bool,
+    pub is_write_enabled: bool,
+    pub compare_op: vk::CompareOp,
 }
 
-pub fn create_framebuffers(
+impl DepthState {
+    /// Tests against and writes the depth buffer, for opaque geometry.
+    pub fn read_write(compare_op: vk::CompareOp) -> Self {
+        DepthState {
+            is_test_enabled: true,
+            is_write_enabled: true,
+            compare_op,
+        }
+    }
+
+    /// Tests without writing, for transparent geometry drawn after the opaque.
+    pub fn read_only(compare_op: vk::CompareOp) -> Self {
+        DepthState {
+            is_write_enabled: false,
+            ..Self::read_write(compare_op)
+        }
+    }
+}
+
+impl Default for DepthState {
+    /// Neither tests nor writes.
+    fn default() -> Self {
+        DepthState {
+            is_test_enabled: false,
+            is_write_enabled: false,
+            compare_op: vk::CompareOp::ALWAYS,
+        }
+    }
+}
+
+/// A graphics pipeline made by `GraphicsPipelineBuilder`.
+pub struct Pipeline {
+    pipeline: vk::Pipeline,
+}
+
+impl Pipeline {
+    pub fn handle(&self) -> vk::Pipeline {
+        self.pipeline
+    }
+
+    /// No frame in flight can be using the pipeline anymore.
+    pub fn destroy(&self, context: &VulkanContext) {
+        unsafe { context.device.destroy_pipeline(self.pipeline, None) };
+    }
+}
+
+/// Describes a graphics pipeline, so each material can have its own shaders and fixed-function
+/// state. Viewport and scissor are always dynamic and set per frame.
+///
+/// ```no_run
+/// # use antithesis::{context::VulkanContext, pipeline::*, shader::Spirv, ash::vk};
+/// # fn build(context: &VulkanContext, layout: &PipelineLayout, render_pass: vk::RenderPass) -> antithesis::error::Result<Pipeline> {
+/// let shader = context.shader_module(&Spirv::load("shaders/sprite.spv")?)?;
+///
+/// GraphicsPipelineBuilder::new(layout)
+///     .shader(vk::ShaderStageFlags::VERTEX, shader, c"vs_main")
+///     .shader(vk::ShaderStageFlags::FRAGMENT, shader, c"fs_main")
+///     .cull_mode(vk::CullModeFlags::BACK)
+///     .blend(BlendMode::Alpha)
+///     .build(context, render_pass)
+/// # }
+/// ```
+pub struct GraphicsPipelineBuilder<'a> {
+    layout: &'a PipelineLayout,
+    shaders: Vec<(vk::ShaderStageFlags, vk::ShaderModule, &'a CStr)>,
+    vertex_layout: VertexLayout,
+    topology: vk::PrimitiveTopology,
+    polygon_mode: vk::PolygonMode,
+    cull_mode: vk::CullModeFlags,
+    front_face: vk::FrontFace,
+    line_width: f32,
+    depth: DepthState,
+    blend: BlendMode,
+}
+
+impl<'a> GraphicsPipelineBuilder<'a> {
+    /// Starts with no vertex input, filled triangle lists, no culling, no depth and no blending.
+    pub fn new(layout: &'a PipelineLayout) -> Self {
+        GraphicsPipelineBuilder {
+            layout,
+            shaders: vec![],
+            vertex_layout: VertexLayout::default(),
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            polygon_mode: vk::PolygonMode::FILL,
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            line_width: 1.0,
+            depth: DepthState::default(),
+            blend: BlendMode::Opaque,
+        }
+    }
+
+    /// Runs `entry_point` of `shader_module` for `stage`. One module can hold the entry points
+    /// of several stages.
+    pub fn shader(
+        mut self,
+        stage: vk::ShaderStageFlags,
+        shader_module: vk::ShaderModule,
+        entry_point: &'a CStr,
+    ) -> Self {
+        self.shaders.push((stage, shader_module, entry_point));
+        self
+    }
+
+    /// A vertex shader with `main` as its entry point.
+    pub fn vertex_shader(self, shader_module: vk::ShaderModule) -> Self {
+        self.shader(vk::ShaderStageFlags::VERTEX, shader_module, c"main")
+    }
+
+    /// A fragment shader with `main` as its entry point.
+    pub fn fragment_shader(self, shader_module: vk::ShaderModule) -> Self {
+        self.shader(vk::ShaderStageFlags::FRAGMENT, shader_module, c"main")
+    }
+
+    /// Usually `V::layout()` for the meshes drawn with the pipeline.
+    pub fn vertex_layout(mut self, vertex_layout: VertexLayout) -> Self {
+        self.vertex_layout = vertex_layout;
+        self
+    }
+
+    pub fn topology(mut self, topology: vk::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// Anything but `FILL` needs the `fillModeNonSolid` feature.
+    pub fn polygon_mode(mut self, polygon_mode: vk::PolygonMode) -> Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: vk::CullModeFlags) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    /// Which winding faces the camera, on screen with y pointing down.
+    pub fn front_face(mut self, front_face: vk::FrontFace) -> Self {
+        self.front_face = front_face;
+        self
+    }
+
+    /// Anything but 1.0 needs the `wideLines` feature.
+    pub fn line_width(mut self, line_width: f32) -> Self {
+        self.line_width = line_width;
+        self
+    }
+
+    pub fn depth(mut self, depth: DepthState) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    pub fn blend(mut self, blend: BlendMode) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    /// Creates the pipeline for subpass 0 of `render_pass`, or any render pass compatible with
+    /// it.
+    pub fn build(&self, context: &VulkanContext, render_pass: vk::RenderPass) -> Result<Pipeline> {
+        let shader_stages: Vec<_> = self
+            .shaders
+            .iter()
+            .map(|&(stage, shader_module, entry_point)| {
+                *vk::PipelineShaderStageCreateInfo::builder()
+                    .module(shader_module)
+                    .name(entry_point)
+                    .stage(stage)
+            })
+            .collect();
+
+        let vertex_input_state_create_info = vk::PipelineVertexInputStateCreateInfo::builder()
+            .vertex_attribute_descriptions(&self.vertex_layout.attributes)
+            .vertex_binding_descriptions(&self.vertex_layout.bindings);
+
+        let input_assembly_state_info =
+            vk::PipelineInputAssemblyStateCreateInfo::builder().topology(self.topology);
+
+        // set per frame instead, so the pipeline survives resizes
+        let viewport_state_create_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_create_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+
+        let rasterization_state_create_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .cull_mode(self.cull_mode)
+            .front_face(self.front_face)
+            .line_width(self.line_width)
+            .polygon_mode(self.polygon_mode);
+
+        // the render passes only have single sampled attachments
+        let multisample_state_create_info = vk::PipelineMultisampleStateCreateInfo::builder()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+        let depth_stencil_state_create_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(self.depth.is_test_enabled)
+            .depth_write_enable(self.depth.is_write_enabled)
+            .depth_compare_op(self.depth.compare_op)
+            .max_depth_bounds(1.0)
+            .min_depth_bounds(0.0);
+
+        let color_blend_attachment_states = [self.blend.attachment_state()];
+        let color_blend_state_create_info = vk::PipelineColorBlendStateCreateInfo::builder()
+            .attachments(&color_blend_attachment_states);
+
+        let gfx_pipeline_create_info = [*vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_state_create_info)
+            .input_assembly_state(&input_assembly_state_info)
+            .viewport_state(&viewport_state_create_info)
+            .rasterization_state(&rasterization_state_create_info)
+            .multisample_state(&multisample_state_create_info)
+            .depth_stencil_state(&depth_stencil_state_create_info)
+            .color_blend_state(&color_blend_state_create_info)
+            .dynamic_state(&dynamic_state_create_info)
+            .layout(self.layout.handle())
+            .render_pass(render_pass)];
+
+        let gfx_pipeline = unsafe {
+            context
+                .device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    &gfx_pipeline_create_info,
+                    None,
+                )
+                .map_err(|(_, result)| result)
+                .context("vkCreateGraphicsPipelines", "graphics pipeline")?
+        };
+
+        Ok(Pipeline {
+            pipeline: gfx_pipeline[0],
+        })
+    }
+}
+
+#[cfg(feature = "compile-shaders")]
+const TRIANGLE_SHADERS: (&[u8], &[u8]) = (crate::shaders::CROAK_VERT, crate::shaders::CROAK_FRAG);
+// compiled by hand with shaders/compile.sh
+#[cfg(not(feature = "compile-shaders"))]
+const TRIANGLE_SHADERS: (&[u8], &[u8]) = (
+    include_bytes!("../shaders/vert.spv"),
+    include_bytes!("../shaders/frag.spv"),
+);
+
+/// What the triangle's shaders are called in shaders/, for hot reloading them.
+#[cfg(feature = "hot-reload")]
+pub(crate) const TRIANGLE_SHADER_NAMES: [&str; 2] = ["croak.vert", "croak.frag"];
+
+/// The built-in triangle's vertex and fragment shader.
+pub(crate) fn triangle_shaders() -> Result<[Spirv; 2]> {
+    let (vert_spirv, frag_spirv) = TRIANGLE_SHADERS;
+    Ok([
+        Spirv::from_bytes(vert_spirv)?,
+        Spirv::from_bytes(frag_spirv)?,
+    ])
+}
+
+/// The pipeline drawing the built-in triangle with `shaders`, with an empty layout.
+pub(crate) fn create_gfx_pipeline(
+    context: &VulkanContext,
+    render_pass: vk::RenderPass,
+    shaders: &[Spirv; 2],
+) -> Result<(PipelineLayout, Pipeline)> {
+    let pipeline_layout = PipelineLayout::new(context, &[], &[])?;
+
+    match create_triangle_pipeline(context, &pipeline_layout, render_pass, shaders) {
+        Ok(gfx_pipeline) => Ok((pipeline_layout, gfx_pipeline)),
+        Err(err) => {
+            pipeline_layout.destroy(context);
+            Err(err)
+        }
+    }
+}
+
+/// Just the pipeline, so hot reloading can swap out the shaders and keep the layout.
+pub(crate) fn create_triangle_pipeline(
+    context: &VulkanContext,
+    pipeline_layout: &PipelineLayout,
+    render_pass: vk::RenderPass,
+    [vert_spirv, frag_spirv]: &[Spirv; 2],
+) -> Result<Pipeline> {
+    let vert_shader = context.shader_module(vert_spirv)?;
+    let frag_shader = context.shader_module(frag_spirv)?;
+
+    GraphicsPipelineBuilder::new(pipeline_layout)
+        .vertex_shader(vert_shader)
+        .fragment_shader(frag_shader)
+        .vertex_layout(ColoredVertex::layout())
+        .cull_mode(vk::CullModeFlags::BACK)
+        .front_face(vk::FrontFace::CLOCKWISE)
+        .build(context, render_pass)
+}
+
+pub(crate) fn create_framebuffers(
     device: &ash::Device,
     render_pass: vk::RenderPass,
-    image_views: &Vec<vk::ImageView>,
+    image_views: &[vk::ImageView],
+    depth_image_view: Option<vk::ImageView>,
     swapchain_extent: &vk::Extent2D,
-) -> Vec<vk::Framebuffer> {
+) -> Result<Vec<vk::Framebuffer>> {
     let mut framebuffers = vec![];
 
     for &image_view in image_views.iter() {
-        let attachments = [image_view];
+        // the depth buffer is shared, only the color image differs between framebuffers
+        let attachments: Vec<_> = [image_view].into_iter().chain(depth_image_view).collect();
 
         let framebuffer_create_info = vk::FramebufferCreateInfo::builder()
             .render_pass(render_pass)
@@ -312,26 +690,78 @@ pub fn create_framebuffers(
         let framebuffer = unsafe {
             device
                 .create_framebuffer(&framebuffer_create_info, None)
-                .expect("Failed to create Framebuffer!")
+                .context("vkCreateFramebuffer", "framebuffer")
         };
 
-        framebuffers.push(framebuffer);
+        match framebuffer {
+            Ok(framebuffer) => framebuffers.push(framebuffer),
+            Err(err) => {
+                for &framebuffer in framebuffers.iter() {
+                    unsafe { device.destroy_framebuffer(framebuffer, None) };
+                }
+                return Err(err);
+            }
+        }
     }
 
-    framebuffers
+    Ok(framebuffers)
 }
 
-fn create_shader_module(
-    device: &ash::Device,
-    file: &mut (impl std::io::Seek + std::io::Read),
-) -> vk::ShaderModule {
-    let code = read_spv(file).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pushes_have_to_match_declared_ranges() {
+        let vertex = vk::ShaderStageFlags::VERTEX;
+        let fragment = vk::ShaderStageFlags::FRAGMENT;
+        let ranges = [
+            push_constant_range::<[f32; 16]>(vertex, 0),
+            push_constant_range::<[f32; 4]>(fragment, 64),
+            push_constant_range::<u32>(vertex | fragment, 80),
+        ];
+
+        assert!(is_push_declared(&ranges, vertex, 0, 64));
+        assert!(is_push_declared(&ranges, vertex, 16, 16));
+        assert!(is_push_declared(&ranges, fragment, 64, 16));
+        assert!(is_push_declared(&ranges, vertex | fragment, 80, 4));
+
+        // past the end of the range
+        assert!(!is_push_declared(&ranges, vertex, 48, 32));
+        // the range is for the other stage
+        assert!(!is_push_declared(&ranges, fragment, 0, 4));
+        // the shared range has to be updated for both stages
+        assert!(!is_push_declared(&ranges, vertex, 80, 4));
+    }
 
-    let create_info = vk::ShaderModuleCreateInfo::builder().code(&code);
+    #[test]
+    fn depth_is_off_unless_asked_for() {
+        let default = DepthState::default();
+        assert!(!default.is_test_enabled && !default.is_write_enabled);
 
-    unsafe {
-        device
-            .create_shader_module(&create_info, None)
-            .expect("Failed to create shader module!")
+        let transparent = DepthState::read_only(vk::CompareOp::LESS);
+        assert!(transparent.is_test_enabled && !transparent.is_write_enabled);
+        assert_eq!(transparent.compare_op, vk::CompareOp::LESS);
+    }
+
+    #[test]
+    fn only_opaque_leaves_blending_off() {
+        assert_eq!(BlendMode::Opaque.attachment_state().blend_enable, vk::FALSE);
+
+        let alpha = BlendMode::Alpha.attachment_state();
+        assert_eq!(alpha.blend_enable, vk::TRUE);
+        assert_eq!(alpha.src_color_blend_factor, vk::BlendFactor::SRC_ALPHA);
+        assert_eq!(
+            BlendMode::Additive
+                .attachment_state()
+                .dst_color_blend_factor,
+            vk::BlendFactor::ONE
+        );
+        assert_eq!(
+            BlendMode::Premultiplied
+                .attachment_state()
+                .src_color_blend_factor,
+            vk::BlendFactor::ONE
+        );
     }
 }
@@ -0,0 +1,46 @@
+pub use antithesis_derive::Pod;
+
+/// Plain data that can be copied into GPU memory byte for byte, like push constants, uniforms
+/// and vertices. Usually implemented with `#[derive(Pod)]`, which refuses structs with padding:
+///
+/// ```compile_fail
+/// use antithesis::pod::Pod;
+///
+/// #[repr(C)]
+/// #[derive(Clone, Copy, Pod)]
+/// struct Light {
+///     intensity: u8,
+///     // 3 bytes of padding before this
+///     radius: f32,
+/// }
+/// ```
+///
+/// # Safety
+///
+/// The type must not have any padding, so that every byte of a value is initialized. Scalars and
+/// arrays of them qualify, and so do `#[repr(C)]` structs made of them without gaps.
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! impl_pod {
+    ($($ty:ty),*) => {
+        // SAFETY: primitive numbers have no padding
+        $(unsafe impl Pod for $ty {})*
+    };
+}
+
+impl_pod!(u8, i8, u16, i16, u32, i32, u64, i64, f32, f64);
+
+// SAFETY: array elements follow each other without gaps, so an array of `Pod` has no padding
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+/// The raw bytes of `data`, for copying into buffers.
+pub(crate) fn as_bytes<T: Pod>(data: &[T]) -> &[u8] {
+    // SAFETY: `T: Pod` has no padding, so all `size_of_val(data)` bytes are initialized, and
+    // `u8` has no alignment requirement
+    unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data)) }
+}
+
+/// The raw bytes of `value`.
+pub(crate) fn bytes_of<T: Pod>(value: &T) -> &[u8] {
+    as_bytes(std::slice::from_ref(value))
+}
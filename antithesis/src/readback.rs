@@ -0,0 +1,302 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use ash::vk;
+
+use crate::{
+    allocator::MemoryLocation,
+    context::VulkanContext,
+    error::{AntithesisError, Result, VkResultExt},
+    pipeline::{create_buffer, destroy_buffer},
+};
+
+/// A rendered frame copied back to the CPU, as tightly packed RGBA8 rows.
+#[derive(Debug, Clone)]
+pub struct FrameCapture {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl FrameCapture {
+    /// The RGBA value of the pixel at (`x`, `y`), counted from the top left.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let offset = ((y * self.width + x) * 4) as usize;
+        let mut pixel = [0; 4];
+        pixel.copy_from_slice(&self.pixels[offset..offset + 4]);
+        pixel
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<()> {
+        let file = File::create(path)?;
+
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+
+        Ok(())
+    }
+}
+
+/// Copies `image` into a host-visible buffer and converts it to RGBA8.
+///
+/// The image has to be created with `TRANSFER_SRC` usage; it's moved out of `layout` for the copy
+/// and put back afterwards, so this works on both presentable and offscreen images.
+pub(crate) fn read_image(
+    context: &VulkanContext,
+    command_pool: vk::CommandPool,
+    image: vk::Image,
+    format: vk::Format,
+    extent: vk::Extent2D,
+    layout: vk::ImageLayout,
+) -> Result<FrameCapture> {
+    if !is_readback_supported(format) {
+        return Err(AntithesisError::UnsupportedFormat(format));
+    }
+
+    let buffer_size = (extent.width * extent.height * 4) as vk::DeviceSize;
+    let (readback_buffer, readback_buffer_allocation) = create_buffer(
+        context,
+        buffer_size,
+        vk::BufferUsageFlags::TRANSFER_DST,
+        MemoryLocation::GpuToCpu,
+    )?;
+
+    let copied = copy_to_buffer(
+        context,
+        command_pool,
+        image,
+        extent,
+        layout,
+        readback_buffer,
+    );
+
+    // the allocation can be bigger than asked for
+    let pixels = copied.map(|_| {
+        let data = &readback_buffer_allocation
+            .mapped_slice()
+            .expect("GpuToCpu memory is always mapped")[..buffer_size as usize];
+        convert_to_rgba8(format, data)
+    });
+
+    destroy_buffer(context, readback_buffer, readback_buffer_allocation);
+
+    Ok(FrameCapture {
+        width: extent.width,
+        height: extent.height,
+        pixels: pixels?,
+    })
+}
+
+/// Copies `image` into `buffer` and waits for the copy to finish.
+fn copy_to_buffer(
+    context: &VulkanContext,
+    command_pool: vk::CommandPool,
+    image: vk::Image,
+    extent: vk::Extent2D,
+    layout: vk::ImageLayout,
+    buffer: vk::Buffer,
+) -> Result<()> {
+    let device = &context.device;
+
+    let command_buffer_allocate_info = vk::CommandBufferAllocateInfo::builder()
+        .command_pool(command_pool)
+        .command_buffer_count(1)
+        .level(vk::CommandBufferLevel::PRIMARY);
+
+    let command_buffers = unsafe {
+        device
+            .allocate_command_buffers(&command_buffer_allocate_info)
+            .context("vkAllocateCommandBuffers", "readback command buffer")?
+    };
+
+    let result = record_copy(device, command_buffers[0], image, extent, layout, buffer)
+        .and_then(|_| submit_and_wait(context, &command_buffers));
+
+    unsafe { device.free_command_buffers(command_pool, &command_buffers) };
+    result
+}
+
+fn record_copy(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    extent: vk::Extent2D,
+    layout: vk::ImageLayout,
+    readback_buffer: vk::Buffer,
+) -> Result<()> {
+    let subresource_range = vk::ImageSubresourceRange {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        base_mip_level: 0,
+        level_count: 1,
+        base_array_layer: 0,
+        layer_count: 1,
+    };
+
+    // wait for rendering to finish writing before the copy reads
+    let to_transfer_barrier = [*vk::ImageMemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+        .old_layout(layout)
+        .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(subresource_range)];
+
+    let from_transfer_barrier = [*vk::ImageMemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::TRANSFER_READ)
+        .dst_access_mask(vk::AccessFlags::empty())
+        .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .new_layout(layout)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(subresource_range)];
+
+    let host_read_barrier = [*vk::BufferMemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(vk::AccessFlags::HOST_READ)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .buffer(readback_buffer)
+        .size(vk::WHOLE_SIZE)];
+
+    // zero row length/height means tightly packed
+    let copy_regions = [*vk::BufferImageCopy::builder()
+        .buffer_offset(0)
+        .buffer_row_length(0)
+        .buffer_image_height(0)
+        .image_subresource(vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        })
+        .image_extent(vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        })];
+
+    let command_buffer_begin_info =
+        vk::CommandBufferBeginInfo::builder().flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+    unsafe {
+        device
+            .begin_command_buffer(command_buffer, &command_buffer_begin_info)
+            .context("vkBeginCommandBuffer", "readback command buffer")?;
+
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &to_transfer_barrier,
+        );
+        device.cmd_copy_image_to_buffer(
+            command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            readback_buffer,
+            &copy_regions,
+        );
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE | vk::PipelineStageFlags::HOST,
+            vk::DependencyFlags::empty(),
+            &[],
+            &host_read_barrier,
+            &from_transfer_barrier,
+        );
+
+        device
+            .end_command_buffer(command_buffer)
+            .context("vkEndCommandBuffer", "readback command buffer")?;
+    }
+
+    Ok(())
+}
+
+fn submit_and_wait(context: &VulkanContext, command_buffers: &[vk::CommandBuffer]) -> Result<()> {
+    let device = &context.device;
+    let submit_infos = [*vk::SubmitInfo::builder().command_buffers(command_buffers)];
+
+    unsafe {
+        let readback_fence = device
+            .create_fence(&vk::FenceCreateInfo::builder(), None)
+            .context("vkCreateFence", "readback fence")?;
+
+        let result = device
+            .queue_submit(context.graphics_queue, &submit_infos, readback_fence)
+            .context("vkQueueSubmit", "readback")
+            .and_then(|_| {
+                device
+                    .wait_for_fences(&[readback_fence], true, u64::MAX)
+                    .context("vkWaitForFences", "readback fence")
+            });
+
+        device.destroy_fence(readback_fence, None);
+        result
+    }
+}
+
+/// Whether `read_image` knows how to turn `format` into RGBA8.
+pub(crate) fn is_readback_supported(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::B8G8R8A8_SRGB
+            | vk::Format::B8G8R8A8_UNORM
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::R8G8B8A8_UNORM
+    )
+}
+
+// sRGB formats already store encoded values, so only the channel order differs
+fn convert_to_rgba8(format: vk::Format, data: &[u8]) -> Vec<u8> {
+    match format {
+        vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM => data
+            .chunks_exact(4)
+            .flat_map(|bgra| [bgra[2], bgra[1], bgra[0], bgra[3]])
+            .collect(),
+        vk::Format::R8G8B8A8_SRGB | vk::Format::R8G8B8A8_UNORM => data.to_vec(),
+        _ => unreachable!("read_image checks the format up front"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swaps_bgra_to_rgba() {
+        let bgra = [1, 2, 3, 4, 10, 20, 30, 40];
+        let capture = FrameCapture {
+            width: 2,
+            height: 1,
+            pixels: convert_to_rgba8(vk::Format::B8G8R8A8_SRGB, &bgra),
+        };
+
+        assert_eq!(capture.pixel(0, 0), [3, 2, 1, 4]);
+        assert_eq!(capture.pixel(1, 0), [30, 20, 10, 40]);
+        assert_eq!(convert_to_rgba8(vk::Format::R8G8B8A8_UNORM, &bgra), bgra);
+    }
+
+    #[test]
+    fn pixels_count_rows_from_the_top() {
+        let capture = FrameCapture {
+            width: 2,
+            height: 2,
+            pixels: (0..16).collect(),
+        };
+
+        assert_eq!(capture.pixel(1, 0), [4, 5, 6, 7]);
+        assert_eq!(capture.pixel(0, 1), [8, 9, 10, 11]);
+    }
+}
@@ -0,0 +1,183 @@
+use std::{collections::HashMap, path::Path};
+
+use ash::vk;
+
+use crate::error::{AntithesisError, Result, VkResultExt};
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+// magic, version, generator, id bound and schema
+const HEADER_WORDS: usize = 5;
+
+/// Checked SPIR-V code, loaded at runtime from a file or from memory.
+///
+/// Turned into a `vk::ShaderModule` with `VulkanContext::shader_module`, which reuses the module
+/// for identical code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spirv {
+    words: Vec<u32>,
+}
+
+impl Spirv {
+    /// Code in either byte order, like a `.spv` file or `include_bytes!`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() % 4 != 0 {
+            return Err(AntithesisError::InvalidShader(
+                "length isn't a multiple of 4 bytes",
+            ));
+        }
+
+        let words = bytes
+            .chunks_exact(4)
+            .map(|word| u32::from_ne_bytes(word.try_into().unwrap()))
+            .collect();
+
+        Self::from_words(words)
+    }
+
+    pub fn from_words(mut words: Vec<u32>) -> Result<Self> {
+        if words.len() < HEADER_WORDS {
+            return Err(AntithesisError::InvalidShader(
+                "too short for a SPIR-V header",
+            ));
+        }
+
+        // the magic number tells which byte order the code was written in
+        if words[0] == SPIRV_MAGIC.swap_bytes() {
+            for word in words.iter_mut() {
+                *word = word.swap_bytes();
+            }
+        } else if words[0] != SPIRV_MAGIC {
+            return Err(AntithesisError::InvalidShader("wrong magic number"));
+        }
+
+        let spirv = Spirv { words };
+        let (major, minor) = spirv.version();
+        if major != 1 || minor > 6 {
+            return Err(AntithesisError::InvalidShader("unknown SPIR-V version"));
+        }
+
+        Ok(spirv)
+    }
+
+    /// Reads a `.spv` file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+
+        let bytes = std::fs::read(path).map_err(|source| AntithesisError::ShaderFile {
+            path: path.to_owned(),
+            source,
+        })?;
+
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn words(&self) -> &[u32] {
+        &self.words
+    }
+
+    /// The SPIR-V version as (major, minor).
+    pub fn version(&self) -> (u32, u32) {
+        let version = self.words[1];
+        ((version >> 16) & 0xff, (version >> 8) & 0xff)
+    }
+}
+
+/// The newest SPIR-V version a device of `api_version` takes without extensions.
+pub(crate) fn max_spirv_version(api_version: u32) -> (u32, u32) {
+    match (
+        vk::api_version_major(api_version),
+        vk::api_version_minor(api_version),
+    ) {
+        (1, 0) => (1, 0),
+        (1, 1) => (1, 3),
+        (1, 2) => (1, 5),
+        _ => (1, 6),
+    }
+}
+
+/// Shader modules by their code, living as long as the context.
+#[derive(Default)]
+pub(crate) struct ShaderCache {
+    modules: HashMap<Vec<u32>, vk::ShaderModule>,
+}
+
+impl ShaderCache {
+    pub(crate) fn get_or_create(
+        &mut self,
+        device: &ash::Device,
+        spirv: &Spirv,
+    ) -> Result<vk::ShaderModule> {
+        if let Some(&shader_module) = self.modules.get(spirv.words()) {
+            return Ok(shader_module);
+        }
+
+        let create_info = vk::ShaderModuleCreateInfo::builder().code(spirv.words());
+        let shader_module = unsafe {
+            device
+                .create_shader_module(&create_info, None)
+                .context("vkCreateShaderModule", "shader module")?
+        };
+
+        self.modules.insert(spirv.words.clone(), shader_module);
+        Ok(shader_module)
+    }
+
+    /// Destroys the module for `spirv`, if there is one.
+    pub(crate) fn remove(&mut self, device: &ash::Device, spirv: &Spirv) {
+        if let Some(shader_module) = self.modules.remove(spirv.words()) {
+            unsafe { device.destroy_shader_module(shader_module, None) };
+        }
+    }
+
+    pub(crate) fn destroy_all(&mut self, device: &ash::Device) {
+        for (_, shader_module) in self.modules.drain() {
+            unsafe { device.destroy_shader_module(shader_module, None) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VERT_SPV: &[u8] = include_bytes!("../shaders/vert.spv");
+
+    #[test]
+    fn loads_either_byte_order() {
+        let spirv = Spirv::from_bytes(VERT_SPV).unwrap();
+        assert_eq!(spirv.words()[0], SPIRV_MAGIC);
+        assert_eq!(spirv.version().0, 1);
+
+        let swapped: Vec<u8> = VERT_SPV
+            .chunks_exact(4)
+            .flat_map(|word| word.iter().rev().copied())
+            .collect();
+        assert_eq!(Spirv::from_bytes(&swapped).unwrap(), spirv);
+    }
+
+    #[test]
+    fn rejects_what_isnt_spirv() {
+        assert!(Spirv::from_bytes(&VERT_SPV[..VERT_SPV.len() - 1]).is_err());
+        assert!(Spirv::from_bytes(&VERT_SPV[..16]).is_err());
+        assert!(Spirv::from_bytes(b"#version 450\n\0\0\0\0\0\0\0\0\0\0\0").is_err());
+
+        let mut words = Spirv::from_bytes(VERT_SPV).unwrap().words().to_vec();
+        words[1] = 0x0002_0000;
+        assert!(Spirv::from_words(words).is_err());
+    }
+
+    #[cfg(feature = "compile-shaders")]
+    #[test]
+    fn build_script_output_is_spirv() {
+        for bytes in [crate::shaders::CROAK_VERT, crate::shaders::CROAK_FRAG] {
+            assert_eq!(Spirv::from_bytes(bytes).unwrap().version(), (1, 0));
+        }
+    }
+
+    #[test]
+    fn newer_vulkan_takes_newer_spirv() {
+        assert_eq!(max_spirv_version(vk::API_VERSION_1_0), (1, 0));
+        assert_eq!(max_spirv_version(vk::API_VERSION_1_1), (1, 3));
+        assert_eq!(max_spirv_version(vk::API_VERSION_1_3), (1, 6));
+    }
+}
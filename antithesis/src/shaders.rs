@@ -0,0 +1,4 @@
+//! The shaders in `shaders/`, compiled to SPIR-V by the build script. Each one is a constant
+//! named after its file, so `croak.vert` is `CROAK_VERT`; load them with `Spirv::from_bytes`.
+
+include!(concat!(env!("OUT_DIR"), "/shaders.rs"));
@@ -2,7 +2,11 @@ use std::ptr;
 
 use ash::vk;
 
-use crate::app::SurfaceInfo;
+use crate::{
+    app::SurfaceInfo,
+    context::VulkanContext,
+    error::{Result, VkResultExt},
+};
 
 pub struct SwapchainInfo {
     pub swapchain_loader: ash::extensions::khr::Swapchain,
@@ -11,6 +15,7 @@ pub struct SwapchainInfo {
     pub swapchain_format: vk::Format,
     pub swapchain_extent: vk::Extent2D,
     pub swapchain_imageviews: Vec<vk::ImageView>,
+    pub swapchain_usage: vk::ImageUsageFlags,
 }
 
 pub struct SwapChainSupportDetail {
@@ -20,26 +25,26 @@ pub struct SwapChainSupportDetail {
 }
 
 impl SwapChainSupportDetail {
-    pub fn query(physical_device: &vk::PhysicalDevice, surface_info: &SurfaceInfo) -> Self {
+    pub fn query(physical_device: &vk::PhysicalDevice, surface_info: &SurfaceInfo) -> Result<Self> {
         unsafe {
             let capabilities = surface_info
                 .surface_loader
                 .get_physical_device_surface_capabilities(*physical_device, surface_info.surface)
-                .expect("Failed to query for surface capabilities.");
+                .context("vkGetPhysicalDeviceSurfaceCapabilitiesKHR", "surface")?;
             let formats = surface_info
                 .surface_loader
                 .get_physical_device_surface_formats(*physical_device, surface_info.surface)
-                .expect("Failed to query for surface formats.");
+                .context("vkGetPhysicalDeviceSurfaceFormatsKHR", "surface")?;
             let present_modes = surface_info
                 .surface_loader
                 .get_physical_device_surface_present_modes(*physical_device, surface_info.surface)
-                .expect("Failed to query for surface present mode.");
+                .context("vkGetPhysicalDeviceSurfacePresentModesKHR", "surface")?;
 
-            SwapChainSupportDetail {
+            Ok(SwapChainSupportDetail {
                 capabilities,
                 formats,
                 present_modes,
-            }
+            })
         }
     }
 
@@ -49,36 +54,39 @@ impl SwapChainSupportDetail {
             if available_format.format == vk::Format::B8G8R8A8_SRGB
                 && available_format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
             {
-                return available_format.clone();
+                return *available_format;
             }
         }
 
-        // return the first format from the list
-        return self.formats.first().unwrap().clone();
+        // return the first format from the list, device selection made sure there is one
+        self.formats[0]
     }
 
-    fn choose_present_mode(&self) -> vk::PresentModeKHR {
-        for &available_present_mode in self.present_modes.iter() {
-            // "Triple buffering" mailbox mode if possible
-            if available_present_mode == vk::PresentModeKHR::MAILBOX {
-                return available_present_mode;
-            }
+    fn choose_present_mode(
+        &self,
+        preferred_present_mode: vk::PresentModeKHR,
+    ) -> vk::PresentModeKHR {
+        if self.present_modes.contains(&preferred_present_mode) {
+            return preferred_present_mode;
         }
 
-        // fallback to "vertical blank"
+        // fallback to "vertical blank", which is always supported
         vk::PresentModeKHR::FIFO
     }
 
-    fn choose_extent(&self) -> vk::Extent2D {
-        if self.capabilities.current_extent.width != u32::max_value() {
+    /// `window_extent` is the window's size in physical pixels, only used when the surface
+    /// leaves the size up to us (e.g. Wayland).
+    fn choose_extent(&self, window_extent: vk::Extent2D) -> vk::Extent2D {
+        if self.capabilities.current_extent.width != u32::MAX {
             self.capabilities.current_extent
         } else {
-            // TODO: remove hard-coded window size
             vk::Extent2D {
-                width: 1280
+                width: window_extent
+                    .width
                     .max(self.capabilities.min_image_extent.width)
                     .min(self.capabilities.max_image_extent.width),
-                height: 720
+                height: window_extent
+                    .height
                     .max(self.capabilities.min_image_extent.height)
                     .min(self.capabilities.max_image_extent.height),
             }
@@ -87,18 +95,20 @@ impl SwapChainSupportDetail {
 }
 
 pub fn create_swapchain(
-    instance: &ash::Instance,
-    device: &ash::Device,
-    physical_device: &vk::PhysicalDevice,
-    surface_info: &SurfaceInfo,
-) -> SwapchainInfo {
-    let swapchain_support = SwapChainSupportDetail::query(physical_device, surface_info);
+    context: &VulkanContext,
+    window_extent: vk::Extent2D,
+    preferred_present_mode: vk::PresentModeKHR,
+    old_swapchain: vk::SwapchainKHR,
+) -> Result<SwapchainInfo> {
+    let device = &context.device;
+    let surface_info = context.surface_info();
+    let swapchain_support = SwapChainSupportDetail::query(&context.physical_device, surface_info)?;
 
     let surface_format = swapchain_support.choose_format();
 
-    let present_mode = swapchain_support.choose_present_mode();
+    let present_mode = swapchain_support.choose_present_mode(preferred_present_mode);
 
-    let swapchain_extent = swapchain_support.choose_extent();
+    let swapchain_extent = swapchain_support.choose_extent(window_extent);
 
     // Just a kinda weird way of getting the image count of the swapchain
     let image_count = swapchain_support.capabilities.min_image_count + 1;
@@ -108,23 +118,26 @@ pub fn create_swapchain(
         image_count
     };
 
-    // let's do it all on one queue for now :(
-    let (image_sharing_mode, _queue_family_index_count, queue_family_indices) =
-        (vk::SharingMode::EXCLUSIVE, 0, vec![]);
-
-    // let (image_sharing_mode, queue_family_index_count, queue_family_indices) =
-    //     if queue_family.graphics_family != queue_family.present_family {
-    //         (
-    //             vk::SharingMode::CONCURRENT,
-    //             2,
-    //             vec![
-    //                 queue_family.graphics_family.unwrap(),
-    //                 queue_family.present_family.unwrap(),
-    //             ],
-    //         )
-    //     } else {
-    //         (vk::SharingMode::EXCLUSIVE, 0, vec![])
-    //     };
+    // images are drawn on the graphics queue and presented on the present queue, so when those
+    // are different families share the images between them instead of transferring ownership
+    let queue_family = &context.queue_families;
+    let (image_sharing_mode, queue_family_indices) =
+        if queue_family.graphics_family != queue_family.present_family {
+            (
+                vk::SharingMode::CONCURRENT,
+                vec![
+                    queue_family.graphics_family.unwrap(),
+                    queue_family.present_family.unwrap(),
+                ],
+            )
+        } else {
+            (vk::SharingMode::EXCLUSIVE, vec![])
+        };
+
+    // copying out of swapchain images (for screenshots) is optional, so only ask where supported
+    let swapchain_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+        | (swapchain_support.capabilities.supported_usage_flags
+            & vk::ImageUsageFlags::TRANSFER_SRC);
 
     // TODO: Construct with a builder!
     let create_info = vk::SwapchainCreateInfoKHR::builder()
@@ -133,68 +146,86 @@ pub fn create_swapchain(
         .image_color_space(surface_format.color_space)
         .image_format(surface_format.format)
         .image_extent(swapchain_extent)
-        .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+        .image_usage(swapchain_usage)
         .image_sharing_mode(image_sharing_mode)
         .queue_family_indices(&queue_family_indices)
         .pre_transform(swapchain_support.capabilities.current_transform)
         .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
         .present_mode(present_mode)
         .clipped(true)
+        // lets the driver hand over resources from the swapchain being replaced, if any
+        .old_swapchain(old_swapchain)
         .image_array_layers(1);
 
-    let swapchain_loader = ash::extensions::khr::Swapchain::new(instance, device);
+    let swapchain_loader = ash::extensions::khr::Swapchain::new(&context.instance, device);
     let swapchain = unsafe {
         swapchain_loader
             .create_swapchain(&create_info, None)
-            .expect("Failed to create the Swapchain!")
+            .context("vkCreateSwapchainKHR", "swapchain")?
     };
 
-    let swapchain_images = unsafe {
+    let images = unsafe {
         swapchain_loader
             .get_swapchain_images(swapchain)
-            .expect("Failed to get Swapchain images.")
+            .context("vkGetSwapchainImagesKHR", "swapchain")
+    };
+    let views = images.and_then(|images| {
+        let views = create_image_views(device, surface_format.format, &images)?;
+        Ok((images, views))
+    });
+    let (swapchain_images, swapchain_imageviews) = match views {
+        Ok(views) => views,
+        Err(err) => {
+            unsafe { swapchain_loader.destroy_swapchain(swapchain, None) };
+            return Err(err);
+        }
     };
 
-    let swapchain_imageviews = create_image_views(device, surface_format.format, &swapchain_images);
-
-    SwapchainInfo {
+    Ok(SwapchainInfo {
         swapchain_loader,
         swapchain,
         swapchain_images,
         swapchain_format: surface_format.format,
         swapchain_extent,
         swapchain_imageviews,
-    }
+        swapchain_usage,
+    })
 }
 
 fn create_image_views(
     device: &ash::Device,
     surface_format: vk::Format,
-    images: &Vec<vk::Image>,
-) -> Vec<vk::ImageView> {
-    let swapchain_imageviews: Vec<vk::ImageView> = images
-        .iter()
-        .map(|&image| {
-            create_image_view(
-                device,
-                image,
-                surface_format,
-                vk::ImageAspectFlags::COLOR,
-                1,
-            )
-        })
-        .collect();
+    images: &[vk::Image],
+) -> Result<Vec<vk::ImageView>> {
+    let mut image_views = Vec::with_capacity(images.len());
+    for &image in images {
+        match create_image_view(
+            device,
+            image,
+            surface_format,
+            vk::ImageAspectFlags::COLOR,
+            1,
+        ) {
+            Ok(image_view) => image_views.push(image_view),
+            Err(err) => {
+                for image_view in image_views {
+                    unsafe { device.destroy_image_view(image_view, None) };
+                }
+                return Err(err);
+            }
+        }
+    }
 
-    swapchain_imageviews
+    Ok(image_views)
 }
 
-fn create_image_view(
+pub fn create_image_view(
     device: &ash::Device,
     image: vk::Image,
     format: vk::Format,
     aspect_flags: vk::ImageAspectFlags,
     mip_levels: u32,
-) -> vk::ImageView {
+) -> Result<vk::ImageView> {
     let imageview_create_info = vk::ImageViewCreateInfo {
         s_type: vk::StructureType::IMAGE_VIEW_CREATE_INFO,
         p_next: ptr::null(),
@@ -220,6 +251,6 @@ fn create_image_view(
     unsafe {
         device
             .create_image_view(&imageview_create_info, None)
-            .expect("Failed to create Image View!")
+            .context("vkCreateImageView", "image view")
     }
 }
@@ -1,136 +1,263 @@
-use crate::device::QueueFamilyIndices;
+use crate::{
+    device::QueueFamilyIndices,
+    error::{Result, VkResultExt},
+};
 
 use ash::vk;
 
-pub const MAX_FRAMES_IN_FLIGHT: usize = 2;
-
 pub struct SyncObjects {
     pub image_available_semaphores: Vec<vk::Semaphore>,
     pub render_finished_semaphores: Vec<vk::Semaphore>,
     pub inflight_fences: Vec<vk::Fence>,
 }
 
-pub fn create_sync_objects(device: &ash::Device) -> SyncObjects {
+pub fn create_sync_objects(device: &ash::Device, frames_in_flight: usize) -> Result<SyncObjects> {
     let mut sync_objects = SyncObjects {
         image_available_semaphores: vec![],
         render_finished_semaphores: vec![],
         inflight_fences: vec![],
     };
 
+    for _ in 0..frames_in_flight {
+        if let Err(err) = create_one_frame_sync_objects(device, &mut sync_objects) {
+            destroy_sync_objects(device, &sync_objects);
+            return Err(err);
+        }
+    }
+
+    Ok(sync_objects)
+}
+
+/// Pushes each object as soon as it exists, so `destroy_sync_objects` gets them on failure.
+fn create_one_frame_sync_objects(
+    device: &ash::Device,
+    sync_objects: &mut SyncObjects,
+) -> Result<()> {
     let semaphore_create_info = vk::SemaphoreCreateInfo::builder();
 
     let fence_create_info = vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED);
 
-    for _ in 0..MAX_FRAMES_IN_FLIGHT {
-        unsafe {
-            let image_available_semaphore = device
-                .create_semaphore(&semaphore_create_info, None)
-                .expect("Failed to create Semaphore Object!");
-            let render_finished_semaphore = device
-                .create_semaphore(&semaphore_create_info, None)
-                .expect("Failed to create Semaphore Object!");
-            let inflight_fence = device
-                .create_fence(&fence_create_info, None)
-                .expect("Failed to create Fence Object!");
-
-            sync_objects
-                .image_available_semaphores
-                .push(image_available_semaphore);
-            sync_objects
-                .render_finished_semaphores
-                .push(render_finished_semaphore);
-            sync_objects.inflight_fences.push(inflight_fence);
+    unsafe {
+        let image_available_semaphore = device
+            .create_semaphore(&semaphore_create_info, None)
+            .context("vkCreateSemaphore", "image available semaphore")?;
+        sync_objects
+            .image_available_semaphores
+            .push(image_available_semaphore);
+
+        let render_finished_semaphore = device
+            .create_semaphore(&semaphore_create_info, None)
+            .context("vkCreateSemaphore", "render finished semaphore")?;
+        sync_objects
+            .render_finished_semaphores
+            .push(render_finished_semaphore);
+
+        let inflight_fence = device
+            .create_fence(&fence_create_info, None)
+            .context("vkCreateFence", "in flight fence")?;
+        sync_objects.inflight_fences.push(inflight_fence);
+    }
+
+    Ok(())
+}
+
+pub fn destroy_sync_objects(device: &ash::Device, sync_objects: &SyncObjects) {
+    unsafe {
+        for &semaphore in sync_objects
+            .image_available_semaphores
+            .iter()
+            .chain(&sync_objects.render_finished_semaphores)
+        {
+            device.destroy_semaphore(semaphore, None);
+        }
+        for &fence in sync_objects.inflight_fences.iter() {
+            device.destroy_fence(fence, None);
         }
     }
+}
 
-    sync_objects
+/// A command pool and buffer for one frame in flight. The pool gets reset and the buffer
+/// re-recorded every time the frame comes around again.
+pub struct FrameCommands {
+    pub command_pool: vk::CommandPool,
+    pub command_buffer: vk::CommandBuffer,
 }
 
-pub fn create_command_buffers(
+pub fn create_frame_commands(
     device: &ash::Device,
-    command_pool: vk::CommandPool,
-    gfx_pipeline: vk::Pipeline,
-    framebuffers: &Vec<vk::Framebuffer>,
-    render_pass: vk::RenderPass,
-    surface_extent: vk::Extent2D,
-    vertex_buffer: vk::Buffer,
-) -> Vec<vk::CommandBuffer> {
+    queue_families: &QueueFamilyIndices,
+    frames_in_flight: usize,
+) -> Result<Vec<FrameCommands>> {
+    let command_pool_create_info = vk::CommandPoolCreateInfo::builder()
+        .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+        .queue_family_index(queue_families.graphics_family.unwrap());
+
+    let mut frame_commands = vec![];
+
+    for _ in 0..frames_in_flight {
+        match create_one_frame_commands(device, &command_pool_create_info) {
+            Ok(commands) => frame_commands.push(commands),
+            Err(err) => {
+                destroy_frame_commands(device, &frame_commands);
+                return Err(err);
+            }
+        }
+    }
+
+    Ok(frame_commands)
+}
+
+fn create_one_frame_commands(
+    device: &ash::Device,
+    command_pool_create_info: &vk::CommandPoolCreateInfo,
+) -> Result<FrameCommands> {
+    let command_pool = unsafe {
+        device
+            .create_command_pool(command_pool_create_info, None)
+            .context("vkCreateCommandPool", "frame command pool")?
+    };
+
     let command_buffer_allocate_info = vk::CommandBufferAllocateInfo::builder()
         .command_pool(command_pool)
-        .command_buffer_count(framebuffers.len() as u32)
+        .command_buffer_count(1)
         .level(vk::CommandBufferLevel::PRIMARY);
 
-    let command_buffers = unsafe {
+    let command_buffer = unsafe {
         device
             .allocate_command_buffers(&command_buffer_allocate_info)
-            .expect("Failed to allocate command buffers!")
+            .context("vkAllocateCommandBuffers", "frame command buffer")
     };
 
-    for (i, &command_buffer) in command_buffers.iter().enumerate() {
-        let command_buffer_begin_info = vk::CommandBufferBeginInfo::builder()
-            .flags(vk::CommandBufferUsageFlags::SIMULTANEOUS_USE);
+    match command_buffer {
+        Ok(command_buffers) => Ok(FrameCommands {
+            command_pool,
+            command_buffer: command_buffers[0],
+        }),
+        Err(err) => {
+            unsafe { device.destroy_command_pool(command_pool, None) };
+            Err(err)
+        }
+    }
+}
 
-        unsafe {
-            device
-                .begin_command_buffer(command_buffer, &command_buffer_begin_info)
-                .expect("Failed to begin recording command buffer at beginning!");
-        };
+pub fn destroy_frame_commands(device: &ash::Device, frame_commands: &[FrameCommands]) {
+    for commands in frame_commands.iter() {
+        // destroying the pool frees its command buffer too
+        unsafe { device.destroy_command_pool(commands.command_pool, None) };
+    }
+}
+
+/// Resets the frame's pool and starts recording into `framebuffer`, leaving the render pass
+/// open with the default pipeline and vertex buffer bound, ready for the game's draws.
+///
+/// The frame's fence has to be waited on first, so the GPU is done with the last recording.
+pub fn begin_frame_commands(
+    device: &ash::Device,
+    frame_commands: &FrameCommands,
+    gfx_pipeline: vk::Pipeline,
+    framebuffer: vk::Framebuffer,
+    render_pass: vk::RenderPass,
+    surface_extent: vk::Extent2D,
+    vertex_buffer: vk::Buffer,
+) -> Result<()> {
+    let command_buffer = frame_commands.command_buffer;
+
+    let command_buffer_begin_info =
+        vk::CommandBufferBeginInfo::builder().flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+    unsafe {
+        device
+            .reset_command_pool(
+                frame_commands.command_pool,
+                vk::CommandPoolResetFlags::empty(),
+            )
+            .context("vkResetCommandPool", "frame command pool")?;
+        device
+            .begin_command_buffer(command_buffer, &command_buffer_begin_info)
+            .context("vkBeginCommandBuffer", "frame command buffer")?;
+    };
 
-        let clear_values = [vk::ClearValue {
+    // the depth value is only used by render passes with a depth attachment
+    let clear_values = [
+        vk::ClearValue {
             color: vk::ClearColorValue {
                 float32: [0.0, 0.0, 0.0, 1.0],
             },
-        }];
-
-        let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
-            .render_pass(render_pass)
-            .framebuffer(framebuffers[i])
-            .render_area(
-                *vk::Rect2D::builder()
-                    .offset(*vk::Offset2D::builder())
-                    .extent(surface_extent),
-            )
-            .clear_values(&clear_values);
-
-        unsafe {
-            device.cmd_begin_render_pass(
-                command_buffer,
-                &render_pass_begin_info,
-                vk::SubpassContents::INLINE,
-            );
-            device.cmd_bind_pipeline(
-                command_buffer,
-                vk::PipelineBindPoint::GRAPHICS,
-                gfx_pipeline,
-            );
-
-            let vertex_buffers = [vertex_buffer];
-            let offsets = [0_u64];
-
-            device.cmd_bind_vertex_buffers(command_buffer, 0, &vertex_buffers, &offsets);
-
-            device.cmd_draw(command_buffer, 3, 1, 0, 0);
-            device.cmd_end_render_pass(command_buffer);
-
-            device
-                .end_command_buffer(command_buffer)
-                .expect("Failed to record command buffer at ending!");
-        }
+        },
+        vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            },
+        },
+    ];
+
+    let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
+        .render_pass(render_pass)
+        .framebuffer(framebuffer)
+        .render_area(
+            *vk::Rect2D::builder()
+                .offset(*vk::Offset2D::builder())
+                .extent(surface_extent),
+        )
+        .clear_values(&clear_values);
+
+    unsafe {
+        device.cmd_begin_render_pass(
+            command_buffer,
+            &render_pass_begin_info,
+            vk::SubpassContents::INLINE,
+        );
+        device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            gfx_pipeline,
+        );
+
+        // the pipeline leaves these dynamic, so they always match the current extent
+        let viewports = [*vk::Viewport::builder()
+            .x(0.0)
+            .y(0.0)
+            .width(surface_extent.width as f32)
+            .height(surface_extent.height as f32)
+            .min_depth(0.0)
+            .max_depth(1.0)];
+        let scissors = [*vk::Rect2D::builder()
+            .offset(*vk::Offset2D::builder())
+            .extent(surface_extent)];
+
+        device.cmd_set_viewport(command_buffer, 0, &viewports);
+        device.cmd_set_scissor(command_buffer, 0, &scissors);
+
+        let vertex_buffers = [vertex_buffer];
+        let offsets = [0_u64];
+
+        device.cmd_bind_vertex_buffers(command_buffer, 0, &vertex_buffers, &offsets);
     }
 
-    command_buffers
+    Ok(())
+}
+
+pub fn end_frame_commands(device: &ash::Device, frame_commands: &FrameCommands) -> Result<()> {
+    unsafe {
+        device.cmd_end_render_pass(frame_commands.command_buffer);
+
+        device
+            .end_command_buffer(frame_commands.command_buffer)
+            .context("vkEndCommandBuffer", "frame command buffer")
+    }
 }
 
 pub fn create_command_pool(
     device: &ash::Device,
     queue_families: &QueueFamilyIndices,
-) -> vk::CommandPool {
+) -> Result<vk::CommandPool> {
     let command_pool_create_info = vk::CommandPoolCreateInfo::builder()
         .queue_family_index(queue_families.graphics_family.unwrap());
 
     unsafe {
         device
             .create_command_pool(&command_pool_create_info, None)
-            .expect("Failed to create Command Pool!")
+            .context("vkCreateCommandPool", "graphics command pool")
     }
 }
@@ -0,0 +1,429 @@
+use ash::vk;
+
+use crate::{
+    allocator::{Allocation, MemoryLocation},
+    context::VulkanContext,
+    error::{AntithesisError, Result, VkResultExt},
+    pipeline::{create_buffer, destroy_buffer},
+    pod::{as_bytes, Pod},
+};
+
+/// A `DEVICE_LOCAL` buffer made by `upload_buffer`. It has to be destroyed before the context.
+pub struct DeviceBuffer {
+    buffer: vk::Buffer,
+    allocation: Allocation,
+    size: vk::DeviceSize,
+}
+
+impl DeviceBuffer {
+    pub fn handle(&self) -> vk::Buffer {
+        self.buffer
+    }
+
+    /// In bytes, as uploaded.
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    /// No frame in flight can be using the buffer anymore.
+    pub fn destroy(self, context: &VulkanContext) {
+        destroy_buffer(context, self.buffer, self.allocation);
+    }
+}
+
+/// Creates a `DEVICE_LOCAL` buffer holding `data`, copied in through a staging buffer on the
+/// transfer queue. Blocks until the copy is done, so it's meant for load time: vertices, instance
+/// data, lookup tables and the like. Errors if `data` is empty.
+///
+/// `usage` is what the buffer is used for afterwards, which also decides what the copy has to
+/// be made visible to.
+pub fn upload_buffer<T: Pod>(
+    context: &VulkanContext,
+    data: &[T],
+    usage: vk::BufferUsageFlags,
+) -> Result<DeviceBuffer> {
+    let data = as_bytes(data);
+    if data.is_empty() {
+        return Err(AntithesisError::EmptyBuffer("device buffer"));
+    }
+    let size = data.len() as vk::DeviceSize;
+
+    let (staging_buffer, mut staging_allocation) = create_buffer(
+        context,
+        size,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        MemoryLocation::CpuToGpu,
+    )?;
+
+    // the allocation can be bigger than asked for
+    staging_allocation
+        .mapped_slice_mut()
+        .expect("CpuToGpu memory is always mapped")[..data.len()]
+        .copy_from_slice(data);
+
+    let buffer_and_allocation = create_buffer(
+        context,
+        size,
+        usage | vk::BufferUsageFlags::TRANSFER_DST,
+        MemoryLocation::GpuOnly,
+    );
+    let (buffer, allocation) = match buffer_and_allocation {
+        Ok(buffer_and_allocation) => buffer_and_allocation,
+        Err(err) => {
+            destroy_buffer(context, staging_buffer, staging_allocation);
+            return Err(err);
+        }
+    };
+
+    let result = copy_buffer(context, staging_buffer, buffer, size, usage);
+    destroy_buffer(context, staging_buffer, staging_allocation);
+
+    match result {
+        Ok(()) => Ok(DeviceBuffer {
+            buffer,
+            allocation,
+            size,
+        }),
+        Err(err) => {
+            destroy_buffer(context, buffer, allocation);
+            Err(err)
+        }
+    }
+}
+
+/// Copies `src` into `dst` on the transfer queue and waits for it.
+///
+/// With a dedicated transfer family, `dst` gets released there and acquired on the graphics
+/// queue, since exclusive buffers belong to one family at a time.
+fn copy_buffer(
+    context: &VulkanContext,
+    src: vk::Buffer,
+    dst: vk::Buffer,
+    size: vk::DeviceSize,
+    usage: vk::BufferUsageFlags,
+) -> Result<()> {
+    let mut commands = UploadCommands::default();
+    let result = record_and_submit_copy(context, &mut commands, src, dst, size, usage);
+    commands.destroy(&context.device);
+
+    result
+}
+
+fn record_and_submit_copy(
+    context: &VulkanContext,
+    commands: &mut UploadCommands,
+    src: vk::Buffer,
+    dst: vk::Buffer,
+    size: vk::DeviceSize,
+    usage: vk::BufferUsageFlags,
+) -> Result<()> {
+    let device = &context.device;
+    let (dst_stage, dst_access) = buffer_usage_scope(usage);
+
+    let transfer_family = context.transfer_family();
+    let graphics_family = context.queue_families.graphics_family.unwrap();
+    let is_ownership_transferred = transfer_family != graphics_family;
+
+    let transfer_command_buffer = commands.begin(device, transfer_family)?;
+
+    let copy_regions = [vk::BufferCopy {
+        src_offset: 0,
+        dst_offset: 0,
+        size,
+    }];
+
+    // either the release half of the ownership transfer, or the whole barrier
+    let (barrier_dst_stage, barrier_dst_access, src_family, dst_family) =
+        if is_ownership_transferred {
+            (
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::AccessFlags::empty(),
+                transfer_family,
+                graphics_family,
+            )
+        } else {
+            (
+                dst_stage,
+                dst_access,
+                vk::QUEUE_FAMILY_IGNORED,
+                vk::QUEUE_FAMILY_IGNORED,
+            )
+        };
+    let transfer_barrier = [*vk::BufferMemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(barrier_dst_access)
+        .src_queue_family_index(src_family)
+        .dst_queue_family_index(dst_family)
+        .buffer(dst)
+        .size(vk::WHOLE_SIZE)];
+
+    unsafe {
+        device.cmd_copy_buffer(transfer_command_buffer, src, dst, &copy_regions);
+        device.cmd_pipeline_barrier(
+            transfer_command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            barrier_dst_stage,
+            vk::DependencyFlags::empty(),
+            &[],
+            &transfer_barrier,
+            &[],
+        );
+        device
+            .end_command_buffer(transfer_command_buffer)
+            .context("vkEndCommandBuffer", "upload command buffer")?;
+    }
+
+    if !is_ownership_transferred {
+        return commands.submit(
+            device,
+            context.transfer_queue,
+            transfer_command_buffer,
+            None,
+            None,
+        );
+    }
+
+    let acquire_command_buffer = commands.begin(device, graphics_family)?;
+
+    let acquire_barrier = [*vk::BufferMemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::empty())
+        .dst_access_mask(dst_access)
+        .src_queue_family_index(transfer_family)
+        .dst_queue_family_index(graphics_family)
+        .buffer(dst)
+        .size(vk::WHOLE_SIZE)];
+
+    unsafe {
+        device.cmd_pipeline_barrier(
+            acquire_command_buffer,
+            dst_stage,
+            dst_stage,
+            vk::DependencyFlags::empty(),
+            &[],
+            &acquire_barrier,
+            &[],
+        );
+        device
+            .end_command_buffer(acquire_command_buffer)
+            .context("vkEndCommandBuffer", "upload command buffer")?;
+    }
+
+    let semaphore = unsafe {
+        device
+            .create_semaphore(&vk::SemaphoreCreateInfo::builder(), None)
+            .context("vkCreateSemaphore", "upload semaphore")?
+    };
+    commands.semaphore = semaphore;
+
+    commands.submit(
+        device,
+        context.transfer_queue,
+        transfer_command_buffer,
+        None,
+        Some(semaphore),
+    )?;
+    commands.submit(
+        device,
+        context.graphics_queue,
+        acquire_command_buffer,
+        Some((semaphore, dst_stage)),
+        None,
+    )
+}
+
+/// Pipeline stages and accesses that can read a buffer created with `usage`, which the upload
+/// has to finish before.
+fn buffer_usage_scope(usage: vk::BufferUsageFlags) -> (vk::PipelineStageFlags, vk::AccessFlags) {
+    let shader_stages = vk::PipelineStageFlags::VERTEX_SHADER
+        | vk::PipelineStageFlags::FRAGMENT_SHADER
+        | vk::PipelineStageFlags::COMPUTE_SHADER;
+
+    let scopes = [
+        (
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+        ),
+        (
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::AccessFlags::INDEX_READ,
+        ),
+        (
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            shader_stages,
+            vk::AccessFlags::UNIFORM_READ,
+        ),
+        (
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            shader_stages,
+            vk::AccessFlags::SHADER_READ,
+        ),
+        (
+            vk::BufferUsageFlags::INDIRECT_BUFFER,
+            vk::PipelineStageFlags::DRAW_INDIRECT,
+            vk::AccessFlags::INDIRECT_COMMAND_READ,
+        ),
+        (
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_READ,
+        ),
+    ];
+
+    let (stages, accesses) = scopes
+        .iter()
+        .filter(|(scope_usage, ..)| usage.intersects(*scope_usage))
+        .fold(
+            (vk::PipelineStageFlags::empty(), vk::AccessFlags::empty()),
+            |(stages, accesses), &(_, stage, access)| (stages | stage, accesses | access),
+        );
+
+    // nothing we know about, so make it visible to everything
+    if stages.is_empty() {
+        (
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::AccessFlags::MEMORY_READ,
+        )
+    } else {
+        (stages, accesses)
+    }
+}
+
+/// Everything one upload creates on the way, so it can all be destroyed however far it got.
+#[derive(Default)]
+struct UploadCommands {
+    command_pools: Vec<vk::CommandPool>,
+    semaphore: vk::Semaphore,
+    fences: Vec<vk::Fence>,
+}
+
+impl UploadCommands {
+    /// Starts recording a one-off command buffer for `queue_family`.
+    fn begin(&mut self, device: &ash::Device, queue_family: u32) -> Result<vk::CommandBuffer> {
+        let command_pool_create_info = vk::CommandPoolCreateInfo::builder()
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+            .queue_family_index(queue_family);
+
+        let command_pool = unsafe {
+            device
+                .create_command_pool(&command_pool_create_info, None)
+                .context("vkCreateCommandPool", "upload command pool")?
+        };
+        self.command_pools.push(command_pool);
+
+        let command_buffer_allocate_info = vk::CommandBufferAllocateInfo::builder()
+            .command_pool(command_pool)
+            .command_buffer_count(1)
+            .level(vk::CommandBufferLevel::PRIMARY);
+
+        let command_buffer_begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        unsafe {
+            let command_buffer = device
+                .allocate_command_buffers(&command_buffer_allocate_info)
+                .context("vkAllocateCommandBuffers", "upload command buffer")?[0];
+            device
+                .begin_command_buffer(command_buffer, &command_buffer_begin_info)
+                .context("vkBeginCommandBuffer", "upload command buffer")?;
+
+            Ok(command_buffer)
+        }
+    }
+
+    /// Submits `command_buffer` to `queue`. Only the last submission gets waited on, the
+    /// earlier ones are chained to it through the semaphore.
+    fn submit(
+        &mut self,
+        device: &ash::Device,
+        queue: vk::Queue,
+        command_buffer: vk::CommandBuffer,
+        wait: Option<(vk::Semaphore, vk::PipelineStageFlags)>,
+        signal: Option<vk::Semaphore>,
+    ) -> Result<()> {
+        let command_buffers = [command_buffer];
+        let wait_semaphores: Vec<_> = wait.iter().map(|&(semaphore, _)| semaphore).collect();
+        let wait_stages: Vec<_> = wait.iter().map(|&(_, stage)| stage).collect();
+        let signal_semaphores: Vec<_> = signal.into_iter().collect();
+
+        let submit_infos = [*vk::SubmitInfo::builder()
+            .command_buffers(&command_buffers)
+            .wait_semaphores(&wait_semaphores)
+            .wait_dst_stage_mask(&wait_stages)
+            .signal_semaphores(&signal_semaphores)];
+
+        unsafe {
+            let fence = device
+                .create_fence(&vk::FenceCreateInfo::builder(), None)
+                .context("vkCreateFence", "upload fence")?;
+
+            // only submitted fences go in the list, the others would never signal
+            let result = device
+                .queue_submit(queue, &submit_infos, fence)
+                .context("vkQueueSubmit", "upload");
+            if let Err(err) = result {
+                device.destroy_fence(fence, None);
+                return Err(err);
+            }
+            self.fences.push(fence);
+
+            // nothing is waited on while something is still being signaled
+            if signal.is_none() {
+                device
+                    .wait_for_fences(&self.fences, true, u64::MAX)
+                    .context("vkWaitForFences", "upload fence")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            // an earlier submission might still be running if a later one failed
+            if !self.fences.is_empty()
+                && device
+                    .wait_for_fences(&self.fences, true, u64::MAX)
+                    .is_err()
+            {
+                let _ = device.device_wait_idle();
+            }
+
+            for &fence in self.fences.iter() {
+                device.destroy_fence(fence, None);
+            }
+            device.destroy_semaphore(self.semaphore, None);
+            // destroying the pools frees their command buffers too
+            for &command_pool in self.command_pools.iter() {
+                device.destroy_command_pool(command_pool, None);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vertex_and_index_buffers_are_read_by_vertex_input() {
+        let (stages, accesses) = buffer_usage_scope(
+            vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::INDEX_BUFFER,
+        );
+
+        assert_eq!(stages, vk::PipelineStageFlags::VERTEX_INPUT);
+        assert_eq!(
+            accesses,
+            vk::AccessFlags::VERTEX_ATTRIBUTE_READ | vk::AccessFlags::INDEX_READ
+        );
+    }
+
+    #[test]
+    fn unknown_usage_is_visible_to_everything() {
+        let (stages, accesses) = buffer_usage_scope(vk::BufferUsageFlags::empty());
+
+        assert_eq!(stages, vk::PipelineStageFlags::ALL_COMMANDS);
+        assert_eq!(accesses, vk::AccessFlags::MEMORY_READ);
+    }
+}
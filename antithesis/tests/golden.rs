@@ -0,0 +1,251 @@
+//! Golden-image tests: render headlessly (lavapipe on CI) and compare against the PNGs in
+//! `tests/golden/`.
+//!
+//! Set `ANTITHESIS_BLESS=1` to overwrite the references with the current output. Machines
+//! without a Vulkan device skip the tests, except on CI (where `CI` is set) or with
+//! `ANTITHESIS_REQUIRE_VULKAN=1`, which fail instead.
+//! Any validation error during rendering or readback fails the test.
+//! Mismatches write `<name>.actual.png` and `<name>.diff.png` to `target/golden/`.
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use antithesis::{
+    config::AppConfig,
+    headless::Renderer,
+    mesh::{Mesh, VertexLayout},
+    pod::Pod,
+    readback::FrameCapture,
+};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 64;
+
+/// Largest per-channel difference that still counts as a matching pixel; software and hardware
+/// rasterizers round the sRGB encode slightly differently.
+const DEFAULT_TOLERANCE: u8 = 2;
+
+#[test]
+fn triangle() {
+    let Some(mut renderer) = headless_renderer(AppConfig::new()) else {
+        return;
+    };
+
+    renderer.render_frame(|frame| frame.draw(3, 1)).unwrap();
+
+    assert_matches_golden(
+        &renderer.read_frame().unwrap(),
+        "triangle",
+        DEFAULT_TOLERANCE,
+    );
+}
+
+/// Matches the built-in triangle's vertices, which is what the default pipeline expects.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, VertexLayout)]
+struct ColoredVertex {
+    pos: [f32; 2],
+    color: [f32; 3],
+}
+
+#[test]
+fn indexed_mesh() {
+    let Some(mut renderer) = headless_renderer(AppConfig::new()) else {
+        return;
+    };
+
+    // the triangle again, with its vertices shuffled and put back in order by the indices
+    let vertices = [
+        ColoredVertex {
+            pos: [-0.5, 0.5],
+            color: [0.0, 0.0, 1.0],
+        },
+        ColoredVertex {
+            pos: [0.0, -0.5],
+            color: [1.0, 0.0, 0.0],
+        },
+        ColoredVertex {
+            pos: [0.5, 0.5],
+            color: [0.0, 1.0, 0.0],
+        },
+    ];
+    let mesh = Mesh::indexed(renderer.context(), &vertices, &[1_u16, 2, 0]).unwrap();
+
+    renderer
+        .render_frame(|frame| frame.draw_mesh(&mesh))
+        .unwrap();
+    let capture = renderer.read_frame().unwrap();
+    mesh.destroy(renderer.context());
+
+    assert_matches_golden(&capture, "triangle", DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn triangle_with_depth_buffer() {
+    let Some(mut renderer) = headless_renderer(AppConfig::new().depth_buffer(true)) else {
+        return;
+    };
+
+    // the default pipeline leaves depth off, so the attachment mustn't change anything
+    renderer.render_frame(|frame| frame.draw(3, 1)).unwrap();
+
+    assert_matches_golden(
+        &renderer.read_frame().unwrap(),
+        "triangle",
+        DEFAULT_TOLERANCE,
+    );
+}
+
+fn headless_renderer(config: AppConfig) -> Option<Renderer> {
+    if !has_vulkan_device() {
+        // a CI job without a device is misconfigured, passing there would test nothing
+        if std::env::var_os("ANTITHESIS_REQUIRE_VULKAN").is_some()
+            || std::env::var_os("CI").is_some()
+        {
+            panic!("No Vulkan device available, but ANTITHESIS_REQUIRE_VULKAN or CI is set.");
+        }
+        // straight to stderr, since the test harness swallows `eprintln!` of passing tests
+        let _ = writeln!(
+            io::stderr(),
+            "Skipping golden test: no Vulkan device available."
+        );
+        return None;
+    }
+
+    // shows validation messages next to the failing test
+    let _ = tracing_subscriber::fmt().with_test_writer().try_init();
+
+    let config = config.size(WIDTH, HEIGHT).strict_validation(true);
+    Some(Renderer::with_config(&config).unwrap())
+}
+
+fn has_vulkan_device() -> bool {
+    let Ok(entry) = (unsafe { ash::Entry::load() }) else {
+        return false;
+    };
+
+    let create_info = ash::vk::InstanceCreateInfo::builder();
+    let Ok(instance) = (unsafe { entry.create_instance(&create_info, None) }) else {
+        return false;
+    };
+
+    let has_device = unsafe { instance.enumerate_physical_devices() }
+        .map(|devices| !devices.is_empty())
+        .unwrap_or(false);
+
+    unsafe { instance.destroy_instance(None) };
+
+    has_device
+}
+
+fn assert_matches_golden(actual: &FrameCapture, name: &str, tolerance: u8) {
+    let reference_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.png", name));
+
+    if std::env::var_os("ANTITHESIS_BLESS").is_some() {
+        actual.save_png(&reference_path).unwrap();
+        return;
+    }
+
+    let expected = load_png(&reference_path);
+    assert_eq!(
+        (actual.width, actual.height),
+        (expected.width, expected.height),
+        "{} has a different size than its reference image",
+        name
+    );
+
+    let (diff, mismatched_pixels) = diff_images(actual, &expected, tolerance);
+    if mismatched_pixels == 0 {
+        return;
+    }
+
+    let output_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"))
+        .parent()
+        .unwrap()
+        .join("golden");
+    std::fs::create_dir_all(&output_dir).unwrap();
+
+    let actual_path = output_dir.join(format!("{}.actual.png", name));
+    let diff_path = output_dir.join(format!("{}.diff.png", name));
+    actual.save_png(&actual_path).unwrap();
+    diff.save_png(&diff_path).unwrap();
+
+    panic!(
+        "{} differs from {} in {} pixels (tolerance {}), see {} and {}",
+        name,
+        reference_path.display(),
+        mismatched_pixels,
+        tolerance,
+        actual_path.display(),
+        diff_path.display()
+    );
+}
+
+/// Compares two same-sized images, returning a diff image (mismatches in red over a dimmed
+/// copy of the reference) and how many pixels were off by more than `tolerance` in any channel.
+fn diff_images(
+    actual: &FrameCapture,
+    expected: &FrameCapture,
+    tolerance: u8,
+) -> (FrameCapture, usize) {
+    let mut mismatched_pixels = 0;
+    let mut pixels = Vec::with_capacity(expected.pixels.len());
+
+    for (actual_pixel, expected_pixel) in actual
+        .pixels
+        .chunks_exact(4)
+        .zip(expected.pixels.chunks_exact(4))
+    {
+        let is_match = actual_pixel
+            .iter()
+            .zip(expected_pixel)
+            .all(|(a, e)| a.abs_diff(*e) <= tolerance);
+
+        if is_match {
+            pixels.extend(expected_pixel[..3].iter().map(|channel| channel / 4));
+            pixels.push(255);
+        } else {
+            mismatched_pixels += 1;
+            pixels.extend([255, 0, 0, 255]);
+        }
+    }
+
+    let diff = FrameCapture {
+        width: expected.width,
+        height: expected.height,
+        pixels,
+    };
+
+    (diff, mismatched_pixels)
+}
+
+fn load_png(path: &PathBuf) -> FrameCapture {
+    let file =
+        File::open(path).unwrap_or_else(|err| panic!("Failed to open {}: {}", path.display(), err));
+
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().unwrap();
+
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).unwrap();
+    buffer.truncate(info.buffer_size());
+
+    assert_eq!(
+        info.color_type,
+        png::ColorType::Rgba,
+        "{} isn't an RGBA image",
+        path.display()
+    );
+
+    FrameCapture {
+        width: info.width,
+        height: info.height,
+        pixels: buffer,
+    }
+}
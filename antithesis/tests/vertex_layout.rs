@@ -0,0 +1,107 @@
+//! Layouts generated by `#[derive(VertexLayout)]`, checked against what they'd be by hand.
+
+use antithesis::{
+    ash::vk,
+    mesh::{Vertex, VertexLayout},
+    pod::Pod,
+};
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, VertexLayout)]
+struct MeshVertex {
+    pos: [f32; 3],
+    normal: [f32; 3],
+    uv: [f32; 2],
+    color: [u8; 4],
+    material: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, VertexLayout)]
+#[vertex(binding = 1, per_instance)]
+struct Instance {
+    #[location(5)]
+    model: [[f32; 4]; 4],
+    #[format(R8G8B8A8_UINT)]
+    flags: [u8; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, VertexLayout)]
+struct Packed(f32, [i16; 2]);
+
+fn formats_and_offsets(layout: &VertexLayout) -> Vec<(u32, u32, vk::Format, u32)> {
+    layout
+        .attributes
+        .iter()
+        .map(|attribute| {
+            (
+                attribute.location,
+                attribute.binding,
+                attribute.format,
+                attribute.offset,
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn maps_field_types_to_formats() {
+    let layout = MeshVertex::layout();
+
+    assert_eq!(layout.bindings.len(), 1);
+    assert_eq!(layout.bindings[0].binding, 0);
+    assert_eq!(layout.bindings[0].stride, 40);
+    assert_eq!(layout.bindings[0].input_rate, vk::VertexInputRate::VERTEX);
+
+    assert_eq!(
+        formats_and_offsets(&layout),
+        [
+            (0, 0, vk::Format::R32G32B32_SFLOAT, 0),
+            (1, 0, vk::Format::R32G32B32_SFLOAT, 12),
+            (2, 0, vk::Format::R32G32_SFLOAT, 24),
+            (3, 0, vk::Format::R8G8B8A8_UNORM, 32),
+            (4, 0, vk::Format::R32_UINT, 36),
+        ]
+    );
+}
+
+#[test]
+fn instance_bindings_with_matrices_and_overrides() {
+    let layout = Instance::layout();
+
+    assert_eq!(layout.bindings[0].binding, 1);
+    assert_eq!(layout.bindings[0].stride, 68);
+    assert_eq!(layout.bindings[0].input_rate, vk::VertexInputRate::INSTANCE);
+
+    // one location per matrix column, and the next field carries on after them
+    assert_eq!(
+        formats_and_offsets(&layout),
+        [
+            (5, 1, vk::Format::R32G32B32A32_SFLOAT, 0),
+            (6, 1, vk::Format::R32G32B32A32_SFLOAT, 16),
+            (7, 1, vk::Format::R32G32B32A32_SFLOAT, 32),
+            (8, 1, vk::Format::R32G32B32A32_SFLOAT, 48),
+            (9, 1, vk::Format::R8G8B8A8_UINT, 64),
+        ]
+    );
+}
+
+#[test]
+fn tuple_structs() {
+    assert_eq!(
+        formats_and_offsets(&Packed::layout()),
+        [
+            (0, 0, vk::Format::R32_SFLOAT, 0),
+            (1, 0, vk::Format::R16G16_SNORM, 4),
+        ]
+    );
+}
+
+#[test]
+fn combines_vertex_and_instance_layouts() {
+    let layout = MeshVertex::layout().with(Instance::layout());
+
+    assert_eq!(layout.bindings.len(), 2);
+    assert_eq!(layout.attributes.len(), 10);
+}